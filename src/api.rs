@@ -1,567 +1,1627 @@
-use std::{vec, collections::HashMap};
+use std::vec;
 use dioxus::prelude::*;
 
-use reqwest::Client;
-use crate::api_models::{NewPlaylistDetails, SpotifyPlaylistItem, SpotifyPlaylistTrackResponse, SpotifyPlaylistsResponse, SpotifyTrackItem, SpotifyUserProfile};
+use crate::app_error::AppError;
+use crate::api_models::{AuthStatus, MergeSource, NewPlaylistDetails, ShuffleDestination, ShuffleStrategyInfo, SpotifyPlaylistItem, SpotifyPlaylistTrackResponse, SpotifyPlaylistsResponse, SpotifyTrackItem, SpotifyUserProfile};
 
 #[cfg(feature="server")]
-use crate::server::AppState;
+use futures::TryStreamExt;
+#[cfg(feature="server")]
+use crate::{app_error::Recovery, api_models::{AddTracksReport, LinkedAccountSummary, MergeContribution, PlaylistItemTrackWrapper, SpotifyPlaylistOwner, LIKED_SONGS_ID}, config::ShuffleDefaults, server::AppState, spotify::{Caller, NewPlaylist, SpotifyClient, SpotifyError}};
+
+#[cfg(feature="server")]
+use rand::{thread_rng, Rng};
+#[cfg(feature="server")]
+use crate::shuffle;
+#[cfg(feature="server")]
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+
 
 #[cfg(feature="server")]
-use rand::{thread_rng, seq::SliceRandom};
+async fn app_state() -> Result<AppState, AppError>{
+    let FromContext(app_state) = extract::<FromContext<AppState>, ()>()
+        .await
+        .map_err(|e| AppError::internal(format!("app state missing: {}", e)))?;
+    Ok(app_state)
+}
 
+#[cfg(feature="server")]
+async fn request_headers() -> Result<axum::http::HeaderMap, AppError>{
+    extract().await.map_err(|e| AppError::internal(format!("request headers missing: {}", e)))
+}
 
-#[server(GetAccessToken)]
-pub async fn get_access_token() -> Result<String, ServerFnError>{
-    let FromContext(app_state) = extract::<FromContext<AppState>, ()>().await?;
+/// The shared Spotify client and the session making this request
+#[cfg(feature="server")]
+async fn spotify() -> Result<(SpotifyClient, Caller), AppError>{
+    let app_state = app_state().await?;
+    let headers = request_headers().await?;
 
-    let tokens_guard = app_state.current_user_tokens.read().unwrap();
-    match &*tokens_guard{
-        Some(tokens) => Ok(tokens.access_token.clone()),
+    match app_state.sessions.session_id_from_headers(&headers){
+        Some(session_id) => Ok((app_state.spotify.clone(), Caller::new(app_state, session_id))),
         None => {
-            tracing::warn!("No access token found in appstate");
-            Err(ServerFnError::ServerError("User not authenticate".to_string()))
+            tracing::warn!("No valid session cookie on request");
+            Err(AppError::Unauthenticated)
         }
+    }
 }
-}
-
-#[server(GetSpotifyUserData)]
-pub async fn get_spotify_user_profile() -> Result<SpotifyUserProfile, ServerFnError>{
-    tracing::info!("Attempting spotify user profile");
 
+#[server(GetAuthStatus)]
+pub async fn get_auth_status() -> Result<AuthStatus, ServerFnError<AppError>>{
+    let app_state = app_state().await?;
+    let headers = request_headers().await?;
 
-    let access_token = get_access_token().await?;
+    let session = app_state
+        .sessions
+        .session_id_from_headers(&headers)
+        .and_then(|session_id| app_state.sessions.status(&session_id));
 
-    let client = Client::new();
-    let profile_endpoint = "https://api.spotify.com/v1/me";
+    Ok(match session{
+        Some((active, linked, scope)) => AuthStatus{
+            logged_in: true,
+            accounts: linked
+                .into_iter()
+                .map(|user| LinkedAccountSummary{
+                    active: user.id == active.id,
+                    spotify_id: user.spotify_id,
+                    display_name: user.display_name,
+                })
+                .collect(),
+            display_name: Some(active.display_name),
+            scopes: scope.split_whitespace().map(str::to_string).collect(),
+        },
+        None => AuthStatus::default(),
+    })
+}
 
-    match client
-        .get(profile_endpoint)
-        .bearer_auth(access_token)
-        .send()
-        .await
-    {
-        Ok(response) => {
-            if response.status().is_success(){
-                match response.json::<SpotifyUserProfile>().await {
-                    Ok(profile) => {
-                        tracing::info!("Succesfully fetched profile: {:?}",profile.display_name);
-                        Ok(profile)
-                    }
-                    Err(e) => {
-                        tracing::error!("failed to parse user profile json {}", e);
-                        Err(ServerFnError::ServerError(format!(
-                            "failed to parse spotify profile: {}",e
-                        )))
-                    }
-                }
-            } else{
-                let status = response.status();
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown Error".to_string());
-                tracing::error!("failed to get user profile from spotify, status:{}, error:{}", status, error_text);
+#[server(GetSpotifyUserData)]
+pub async fn get_spotify_user_profile() -> Result<SpotifyUserProfile, ServerFnError<AppError>>{
+    tracing::info!("Attempting spotify user profile");
 
-                Err(ServerFnError::ServerError(format!(
-                    "Spotify API Error ({}): {}", status, error_text)))
-            }
-        }
-        Err(e) => {
-            tracing::error!("Network Error while fetching user profile: {}",e);
-            Err(ServerFnError::ServerError(format!("Network Error: {}", e)))
-        }
-    }
+    let (spotify, caller) = spotify().await?;
+    let profile = spotify.me(&caller).await.map_err(AppError::from)?;
+    tracing::info!("Succesfully fetched profile: {:?}",profile.display_name);
+    Ok(profile)
 }
 
 #[server(GetSpotifyUserId)]
-pub async fn get_spotify_user_id() -> Result<String, ServerFnError>{
+pub async fn get_spotify_user_id() -> Result<String, ServerFnError<AppError>>{
     let profile = get_spotify_user_profile().await?;
     Ok(profile.id)
 }
 
 #[server(GetSpotifyUserPlaylistsPage)]
-pub async fn get_spotify_user_playlists_page(limit: u32, offset:u32) -> Result<SpotifyPlaylistsResponse, ServerFnError>{
+pub async fn get_spotify_user_playlists_page(limit: u32, offset:u32) -> Result<SpotifyPlaylistsResponse, ServerFnError<AppError>>{
     tracing::info!("Attempting spotify user playlists page offset: {}", offset);
 
-    let access_token = get_access_token().await?;
-
-    let client = Client::new();
-    let mut playlist_url = reqwest::Url::parse("https://api.spotify.com/v1/me/playlists").unwrap();
-    playlist_url.query_pairs_mut()
-        .append_pair("limit",&limit.to_string())
-        .append_pair("offset", &offset.to_string());
-
-    match client
-        .get(playlist_url)
-        .bearer_auth(access_token)
-        .send()
-        .await
-    {
-        Ok(response) => {
-            if response.status().is_success(){
-                match response.json::<SpotifyPlaylistsResponse>().await {
-                    Ok(page_data) => {
-                        tracing::info!(
-                            "Successfully fetched page of {} playlists. Offset: {}",
-                            page_data.items.len(),
-                            page_data.offset);
-                        Ok(page_data)
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to parse playlist json: {}", e);
-                        Err(ServerFnError::ServerError(format!(
-                            "failed to parse spotify playlists: {}",e
-                        )))
-                    }
-                }
-            } else{
-                let status = response.status();
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown Error".to_string());
-                tracing::error!("failed to get user playlists from spotify, status:{}, error:{}", status, error_text);
-
-                Err(ServerFnError::ServerError(format!(
-                    "Spotify API Error ({}): {}", status, error_text)))
-            }
-        }
-        Err(e) => {
-            tracing::error!("Network Error while fetching user playlists: {}",e);
-            Err(ServerFnError::ServerError(format!("Network Error: {}", e)))
-        }
-    }
+    let (spotify, caller) = spotify().await?;
+    let page_data = spotify.playlists_page(&caller, limit, offset).await.map_err(AppError::from)?;
+    tracing::info!(
+        "Successfully fetched page of {} playlists. Offset: {}",
+        page_data.items.len(),
+        page_data.offset);
+    Ok(page_data)
 }
 
+/// Every playlist of the user's, with their Liked Songs (if any) first as a playlist of its own
 #[server(GetSpotifyUserPlaylistsAll)]
-pub async fn get_spotify_user_playlists_all() -> Result<Vec<SpotifyPlaylistItem>, ServerFnError>{
-    
-    let mut all_playlists:Vec<SpotifyPlaylistItem> = vec![];
+pub async fn get_spotify_user_playlists_all() -> Result<Vec<SpotifyPlaylistItem>, ServerFnError<AppError>>{
+    let (spotify, caller) = spotify().await?;
+    // a session from before the library scope was asked for still gets its playlists, the
+    // shuffle page offers to reconnect for Liked Songs
+    let liked_songs = source_playlist(&spotify, &caller, LIKED_SONGS_ID)
+        .await
+        .inspect_err(|e| tracing::warn!("Leaving Liked Songs out of the playlists: {}", e))
+        .ok()
+        .filter(|liked_songs| liked_songs.tracks.total > 0);
 
-    let mut current_limit: u32 = 50;
-    let mut current_offset: u32 = 0;
-
-    loop {
-        match get_spotify_user_playlists_page(current_limit, current_offset).await {
-            Ok(page_response) =>{
-                // if page_response.items.is_empty() && page_response.next.is_none(){
-                //     tracing::info!("No more playlists to fetch.");
-                //     break;
-                // }
-                let num_items = page_response.items.len();
-                all_playlists.extend(page_response.items);
-                tracing::info!("Page: {}, Fetched {} playlists, total now: {}. Offset was: {}, total playlists to fetch:{}",
-                    current_offset/50,
-                    num_items,
-                    all_playlists.len(),
-                    current_offset,
-                    page_response.total
-                );
-
-               let next_url = match page_response.next {
-                    Some(s) => s,
-                    None => break
-                };
-                let url = reqwest::Url::parse(&next_url)?;
-                let params: HashMap<_,_> = url.query_pairs().into_owned().collect();
-
-
-                current_offset = match params.get("offset") {
-                    Some(offset_str) => match offset_str.parse::<u32>() {
-                        Ok(num) => num,
-                        Err(e) => {
-                            tracing::error!("Failed to parse 'offset' from next_url query ('{}'): {}", offset_str, e);
-                            return Err(ServerFnError::ServerError(format!("Invalid 'offset' in next URL: {}", e)));
-                        }
-                    },
-                    None => {
-                        tracing::warn!("'offset' not found in next_url query: {}. Assuming end or error.", next_url);
-                        return Err(ServerFnError::ServerError("Missing 'offset' in Spotify's next URL".to_string()));
-                    }
-                };
-                current_limit = match params.get("limit") {
-                    Some(limit_str) => match limit_str.parse::<u32>() {
-                        Ok(num) => num,
-                        Err(e) => {
-                            tracing::error!("Failed to parse 'offset' from next_url query ('{}'): {}", limit_str, e);
-                            return Err(ServerFnError::ServerError(format!("Invalid 'offset' in next URL: {}", e)));
-                        }
-                    },
-                    None => {
-                        tracing::warn!("'offset' not found in next_url query: {}. Assuming end or error.", next_url);
-                        return Err(ServerFnError::ServerError("Missing 'offset' in Spotify's next URL".to_string()));
-                    }
-                };
-            
-            }
-            Err(e) =>{
-                tracing::error!("error fetcing page of playlists: {}",e);
-                return Err(e)
-            }
-        }
-    }
+    let mut all_playlists: Vec<SpotifyPlaylistItem> = spotify.playlists(&caller).try_collect().await.map_err(|e| {
+        tracing::error!("error fetcing page of playlists: {}",e);
+        AppError::from(e)
+    })?;
 
     let mut unique_checker = std::collections::HashSet::new(); 
     all_playlists.retain(|p| unique_checker.insert(p.id.clone()));
+    all_playlists.splice(0..0, liked_songs);
     tracing::info!("Finished fetching. Total playlists retrieved: {}", all_playlists.len());
     Ok(all_playlists)
 }
 
 #[server(GetSpotifyPlaylistTracksAll)]
-pub async fn get_spotify_playlist_tracks_all(playlist_id: String) -> Result<Vec<SpotifyTrackItem>,ServerFnError>{
+pub async fn get_spotify_playlist_tracks_all(playlist_id: String) -> Result<Vec<SpotifyTrackItem>, ServerFnError<AppError>>{
     tracing::info!("Attempting to get tracks for playlist:{}",playlist_id);
-    
+    let (spotify, caller) = spotify().await?;
 
-    let mut all_tracks: Vec<SpotifyTrackItem> = vec![];
-    let mut page_tracks: Vec<SpotifyTrackItem> = vec![];
-    let mut current_offset: u32 = 0;
-    let LIMIT :u32 = 50;
-    
-    loop {
-        match get_spotify_playlist_tracks_page(playlist_id.clone(), LIMIT, current_offset).await {
-            Ok(page_response) => {
-                if page_response.items.is_empty() && page_response.next.is_none(){
-                    tracing::info!("No more tracks on this page and no more next page");
-                    break;
-                }
-                let num_items = page_response.items.len();
-                page_tracks = page_response.items
-                    .into_iter()
-                    .filter_map(|item_wrapper| item_wrapper.track)
-                    .collect();
-                all_tracks.extend(page_tracks);
-
-                tracing::info!("Page: {}, Fetched {} tracks, total now: {}. Offset was: {}, total playlists to fetch:{}",
-                                    current_offset/LIMIT,
-                                    num_items,
-                                    all_tracks.len(),
-                                    current_offset,
-                                    page_response.total
-                                );
-
-                let next_url = match page_response.next {
-                    Some(s) => s,
-                    None => break
-                };
-                let url = reqwest::Url::parse(&next_url)?;
-                let params: HashMap<_,_> = url.query_pairs().into_owned().collect();
-
-
-                current_offset = match params.get("offset") {
-                    Some(offset_str) => match offset_str.parse::<u32>() {
-                        Ok(num) => num,
-                        Err(e) => {
-                            tracing::error!("Failed to parse 'offset' from next_url query ('{}'): {}", offset_str, e);
-                            return Err(ServerFnError::ServerError(format!("Invalid 'offset' in next URL: {}", e)));
-                        }
-                    },
-                    None => {
-                        tracing::warn!("'offset' not found in next_url query: {}. Assuming end or error.", next_url);
-                        return Err(ServerFnError::ServerError("Missing 'offset' in Spotify's next URL".to_string()));
-                    }
-                };
+    let all_tracks: Vec<SpotifyTrackItem> = source_items(&spotify, &caller, &playlist_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching page of tracks:{}",e);
+            AppError::from(e)
+        })?
+        .into_iter()
+        .filter_map(|item_wrapper| item_wrapper.track)
+        .collect();
 
-            }
-            Err(e) => {
-                tracing::error!("Error fetching page of tracks:{}",e);
-                return Err(e)
-            }
-        }
-    }
     tracing::info!("Finished fetching. Total tracks retrieved: {}", all_tracks.len());
     Ok(all_tracks)
-
 }
 
 #[server(GetSpotifyPlaylist)]
-pub async fn get_spotify_playlist(playlist_id: String) -> Result<SpotifyPlaylistItem, ServerFnError> {
+pub async fn get_spotify_playlist(playlist_id: String) -> Result<SpotifyPlaylistItem, ServerFnError<AppError>> {
     tracing::info!("Attempting to get playlist details for ID: {}", playlist_id);
-    
-    let access_token = get_access_token().await?;
-    
-    let client = Client::new();
-    let playlist_url = format!("https://api.spotify.com/v1/playlists/{}", playlist_id);
-    
-    match client
-        .get(&playlist_url)
-        .bearer_auth(access_token)
-        .send()
+
+    let (spotify, caller) = spotify().await?;
+    let playlist = source_playlist(&spotify, &caller, &playlist_id).await.map_err(AppError::from)?;
+    tracing::info!("Successfully fetched playlist: {}", playlist.name);
+    Ok(playlist)
+}
+
+#[server(GetSpotifyPlaylistTracksPage)]
+pub async fn get_spotify_playlist_tracks_page(playlist_id: String, limit: u32, offset:u32) -> Result<SpotifyPlaylistTrackResponse, ServerFnError<AppError>>{
+    tracing::info!("Attempting spotify playlist tracks page offset: {}", offset);
+
+    let (spotify, caller) = spotify().await?;
+    let page_data = if playlist_id == LIKED_SONGS_ID {
+        spotify.saved_tracks_page(&caller, limit, offset).await
+    } else {
+        spotify.playlist_tracks_page(&caller, &playlist_id, limit, offset).await
+    }
+    .map_err(AppError::from)?;
+    tracing::info!(
+        "Successfully fetched page of {} tracks. Offset: {}",
+        page_data.items.len(),
+        page_data.offset);
+    Ok(page_data)
+}
+
+/// The registered shuffle strategies, the default first
+#[server(GetShuffleStrategies)]
+pub async fn get_shuffle_strategies() -> Result<Vec<ShuffleStrategyInfo>, ServerFnError<AppError>>{
+    Ok(shuffle::STRATEGIES.iter().map(|strategy| strategy.info()).collect())
+}
+
+/// `strategy` is the name of one of [`get_shuffle_strategies`]. The same `seed` and strategy give
+/// the same order again as long as the source playlist is unchanged; without one a seed is picked.
+/// `destination` decides whether an earlier shuffled copy is reused, a new playlist is created or
+/// the playlist itself is reordered.
+#[server(ShuffleAndSavePlaylist)]
+pub async fn shuffle_and_save_new_playlist(
+    original_playlist_id: String,
+    original_playlist_name: String,
+    strategy: String,
+    seed: Option<u64>,
+    destination: ShuffleDestination,
+) -> Result<NewPlaylistDetails, ServerFnError<AppError>> {
+    tracing::info!("API: Server-side shuffle for playlist: '{}' (ID: {})", original_playlist_name, original_playlist_id);
+    let app_state = app_state().await?;
+    let defaults = &app_state.config.shuffle;
+    let strategy = shuffle::find(&strategy)
+        .ok_or_else(|| AppError::validation(format!("Unknown shuffle strategy '{}'.", strategy)))?;
+
+    // 1. Get Current User's Spotify ID
+    let user_id = get_spotify_user_id().await.inspect_err(|e| tracing::error!("API: Failed to get user ID: {}", e))?;
+    tracing::info!("API: Target user ID: {}", user_id);
+
+    // 2. Fetch the Original Playlist and All its Tracks. The snapshot identifies the version a seed reproduces.
+    let (spotify, caller) = spotify().await?;
+    let original_playlist = source_playlist(&spotify, &caller, &original_playlist_id).await.map_err(AppError::from)?;
+    let seed = seed.unwrap_or_else(|| thread_rng().gen());
+    if destination == ShuffleDestination::InPlace {
+        return shuffle_in_place(&spotify, &caller, &app_state, &user_id, original_playlist, strategy, seed)
+            .await
+            .map_err(Into::into);
+    }
+    let tracks_for_shuffling = get_spotify_playlist_tracks_all(original_playlist_id.clone())
         .await
-    {
-        Ok(response) => {
-            if response.status().is_success() {
-                match response.json::<SpotifyPlaylistItem>().await {
-                    Ok(playlist) => {
-                        tracing::info!("Successfully fetched playlist: {}", playlist.name);
-                        Ok(playlist)
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to parse playlist json: {}", e);
-                        Err(ServerFnError::ServerError(format!(
-                            "Failed to parse Spotify playlist: {}", e
-                        )))
-                    }
-                }
-            } else {
-                let status = response.status();
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown Error".to_string());
-                tracing::error!("Failed to get playlist from Spotify, status:{}, error:{}", status, error_text);
-                
-                Err(ServerFnError::ServerError(format!(
-                    "Spotify API Error ({}): {}", status, error_text)))
+        .inspect_err(|e| tracing::error!("API: Failed to fetch tracks for '{}': {}", original_playlist_name, e))?;
+
+    if tracks_for_shuffling.is_empty() {
+        return Err(AppError::validation(format!("Playlist '{}' is empty.", original_playlist_name)).into());
+    }
+    tracing::info!("API: Fetched {} tracks for '{}'.", tracks_for_shuffling.len(), original_playlist_name);
+
+    // 3. Shuffle, then extract URIs. Tracks and episodes keep their own URI; local files
+    // can't be added through the API, so they are left out and reported as skipped.
+    let (local_files, playable): (Vec<SpotifyTrackItem>, Vec<SpotifyTrackItem>) =
+        tracks_for_shuffling.into_iter().partition(|track| track.is_local);
+    let skipped: Vec<String> = local_files.into_iter().map(|track| track.uri).collect();
+
+    if playable.is_empty() {
+        return Err(AppError::validation("The playlist only has local files, which Spotify doesn't let us add to a new playlist.").into());
+    }
+
+    let shuffled = strategy.shuffle(playable, &mut shuffle::seeded_rng(seed));
+    let summary = shuffle::summarize(strategy, &shuffled, seed, &original_playlist.snapshot_id);
+    tracing::info!("API: Same artist at least {:?} apart, same album at least {:?} apart", summary.min_artist_gap, summary.min_album_gap);
+    let track_uris: Vec<String> = shuffled.into_iter().map(|track| track.uri).collect();
+    tracing::info!("API: Shuffled {} track URIs with '{}', seed {}.", track_uris.len(), strategy.name(), seed);
+
+    // 4. Find the Playlist to Save to: the earlier shuffled copy, or a new one
+    let new_playlist_name = format!("{}{}", original_playlist_name, defaults.playlist_suffix);
+    let description = format!(
+        "{} shuffle of '{}'! Seed {} {}",
+        strategy.label(),
+        original_playlist_name,
+        seed,
+        copy_marker(&original_playlist_id)
+    );
+    let existing_copy = match destination {
+        ShuffleDestination::ShuffledCopy => {
+            find_shuffled_copy(&spotify, &caller, &app_state, &user_id, &original_playlist_id).await?
+        }
+        ShuffleDestination::NewPlaylist | ShuffleDestination::InPlace => None,
+    };
+    let saved_to = if existing_copy.is_some() { ShuffleDestination::ShuffledCopy } else { ShuffleDestination::NewPlaylist };
+
+    let target_playlist = match existing_copy {
+        Some(copy) => {
+            tracing::info!("API: Re-shuffling existing copy '{}' (ID: {})", copy.name, copy.id);
+            // the seed changes every time, the items are what matter though
+            if let Err(e) = spotify.set_description(&caller, &copy.id, &description).await {
+                tracing::warn!("API: Failed to update the copy's description: {}", e);
             }
+            copy
         }
-        Err(e) => {
-            tracing::error!("Network Error while fetching playlist: {}", e);
-            Err(ServerFnError::ServerError(format!("Network Error: {}", e)))
+        None => {
+            tracing::info!("API: Creating new playlist: {}", new_playlist_name);
+            let created = spotify
+                .create_playlist(&caller, &user_id, &NewPlaylist {
+                    name: &new_playlist_name,
+                    public: defaults.public,
+                    description: &description,
+                })
+                .await
+                .map_err(|e| {
+                    tracing::error!("API: Failed to create playlist: {}", e);
+                    AppError::from(e)
+                })?;
+            tracing::info!("API: New playlist created '{}' (ID: {})", created.name, created.id);
+            created
+        }
+    };
+    let new_playlist_id = target_playlist.id.clone();
+    // an extra new playlist only becomes the copy to update when there is none yet
+    let remember = destination == ShuffleDestination::ShuffledCopy
+        || matches!(app_state.storage.shuffled_copy(&user_id, &original_playlist_id), Ok(None));
+    if remember {
+        if let Err(e) = app_state.storage.remember_shuffled_copy(&user_id, &original_playlist_id, &new_playlist_id) {
+            tracing::error!("API: Failed to remember the shuffled copy: {}", e);
         }
     }
+
+    // 5. Put the Shuffled Tracks in the Playlist (in batches), checking every chunk landed
+    let mut report = if saved_to == ShuffleDestination::ShuffledCopy {
+        replace_in_chunks(&spotify, &caller, &new_playlist_id, &track_uris, defaults).await?
+    } else {
+        add_in_chunks(&spotify, &caller, &new_playlist_id, &track_uris, defaults, AddTracksReport::default()).await?
+    };
+    report.skipped = skipped;
+    if report.is_complete() {
+        tracing::info!("API: All tracks saved to playlist: {}", target_playlist.name);
+    } else {
+        tracing::warn!(
+            "API: Playlist {} is incomplete: {} added, {} failed, {} skipped, Spotify reports {:?}",
+            target_playlist.name,
+            report.added.len(),
+            report.failed.len(),
+            report.skipped.len(),
+            report.verified_total
+        );
+    }
+
+    // 6. Copy the original playlist's image to the new playlist, a failure here doesn't fail the shuffle
+    if let Err(e) = copy_cover(&spotify, &caller, &original_playlist, &new_playlist_id).await {
+        tracing::error!("API: Failed to copy cover image: {}", e);
+    }
+
+    // 7. Return Success
+    let web_url = target_playlist
+        .external_urls
+        .spotify
+        .unwrap_or_else(|| format!("https://open.spotify.com/playlist/{}", new_playlist_id));
+    Ok(NewPlaylistDetails {
+        id: new_playlist_id,
+        name: target_playlist.name,
+        external_url: web_url,
+        saved_to,
+        report,
+        summary,
+        merged_from: Vec::new(),
+    })
 }
 
-#[server(GetSpotifyPlaylistTracksPage)]
-pub async fn get_spotify_playlist_tracks_page(playlist_id: String, limit: u32, offset:u32) -> Result<SpotifyPlaylistTrackResponse, ServerFnError>{
-    tracing::info!("Attempting spotify playlist tracks page offset: {}", offset);
+/// Most Spotify keeps of a playlist description
+#[cfg(feature="server")]
+const MAX_DESCRIPTION_CHARS: usize = 300;
 
-    let access_token = get_access_token().await?;
+/// Shuffles the tracks of every source into one new playlist. With `dedupe` a track only
+/// goes in once, counted for the first source that has it; with weights each source is
+/// sampled down to its share (see [`MergeSource`]). `strategy` and `seed` work as in
+/// [`shuffle_and_save_new_playlist`].
+#[server(MergeAndShufflePlaylists)]
+pub async fn merge_and_shuffle_playlists(
+    sources: Vec<MergeSource>,
+    dedupe: bool,
+    strategy: String,
+    seed: Option<u64>,
+) -> Result<NewPlaylistDetails, ServerFnError<AppError>> {
+    let app_state = app_state().await?;
+    let defaults = &app_state.config.shuffle;
+    let strategy = shuffle::find(&strategy)
+        .ok_or_else(|| AppError::validation(format!("Unknown shuffle strategy '{}'.", strategy)))?;
+    if sources.len() < 2 {
+        return Err(AppError::validation("Pick at least two playlists to merge.").into());
+    }
+    let weights: Option<Vec<u32>> = sources.iter().map(|source| source.weight).collect();
+    match &weights {
+        Some(weights) if weights.contains(&0) => return Err(AppError::validation("Every weight has to be above 0.").into()),
+        None if sources.iter().any(|source| source.weight.is_some()) => {
+            return Err(AppError::validation("Give every playlist a weight, or none of them.").into())
+        }
+        _ => {}
+    }
 
-    const FIELDS: &str = "items(track(id,name,uri)),limit,offset,total,next";
+    let user_id = get_spotify_user_id().await?;
+    let (spotify, caller) = spotify().await?;
+    let seed = seed.unwrap_or_else(|| thread_rng().gen());
+    tracing::info!("API: Merging {} playlists, dedupe {}, weights {:?}", sources.len(), dedupe, weights);
 
-    let client = Client::new();
-    let mut tracks_url = reqwest::Url::parse(
-        format!("https://api.spotify.com/v1/playlists/{}/tracks",playlist_id).as_str()).unwrap();
-    tracks_url.query_pairs_mut()
-        .append_pair("offset", &offset.to_string())
-        .append_pair("limit", &limit.to_string())
-        .append_pair("fields", FIELDS);
+    // 1. Fetch every source, local files can't be added so they are set aside
+    let mut snapshot_ids = Vec::with_capacity(sources.len());
+    let mut tracks_per_source = Vec::with_capacity(sources.len());
+    let mut skipped = Vec::new();
+    for source in &sources {
+        let playlist = source_playlist(&spotify, &caller, &source.playlist_id).await.map_err(AppError::from)?;
+        snapshot_ids.push(playlist.snapshot_id);
+        let (local_files, playable): (Vec<SpotifyTrackItem>, Vec<SpotifyTrackItem>) = source_items(&spotify, &caller, &source.playlist_id)
+            .await
+            .map_err(AppError::from)?
+            .into_iter()
+            .filter_map(|item| item.track)
+            .partition(|track| track.is_local);
+        skipped.extend(local_files.into_iter().map(|track| track.uri));
+        tracks_per_source.push(playable);
+    }
 
+    // 2. Combine them, then shuffle the union
+    let duplicates = if dedupe { shuffle::merge::dedupe(&mut tracks_per_source) } else { vec![0; sources.len()] };
+    let mut rng = shuffle::seeded_rng(seed);
+    if let Some(weights) = &weights {
+        // a source with nothing left can't fill any share, which would leave every other one empty too
+        if let Some((source, _)) = sources.iter().zip(&tracks_per_source).find(|(_, tracks)| tracks.is_empty()) {
+            let reason = if dedupe { "once duplicates and local files are left out" } else { "once local files are left out" };
+            return Err(AppError::validation(format!(
+                "'{}' has no tracks {}, so it can't make up its share of the mix. Remove it or merge without weights.",
+                source.name, reason
+            ))
+            .into());
+        }
+        tracks_per_source = shuffle::merge::weigh(tracks_per_source, weights, &mut rng);
+    }
+    let merged_from: Vec<MergeContribution> = sources
+        .iter()
+        .zip(&tracks_per_source)
+        .zip(duplicates)
+        .map(|((source, tracks), duplicates)| MergeContribution { name: source.name.clone(), taken: tracks.len() as u32, duplicates })
+        .collect();
+    let union: Vec<SpotifyTrackItem> = tracks_per_source.into_iter().flatten().collect();
+    if union.is_empty() {
+        return Err(AppError::validation("None of the playlists have tracks that can be added.").into());
+    }
+    let shuffled = strategy.shuffle(union, &mut rng);
+    let summary = shuffle::summarize(strategy, &shuffled, seed, &snapshot_ids.join(","));
+    let track_uris: Vec<String> = shuffled.into_iter().map(|track| track.uri).collect();
 
-    match client
-        .get(tracks_url)
-        .bearer_auth(access_token)
-        .send()
+    // 3. Save it to a new playlist whose description lists the sources
+    // summed wide like `weigh` does, the weights are whatever the user typed
+    let weight_sum: u64 = weights.iter().flatten().map(|weight| *weight as u64).sum();
+    let listed: Vec<String> = sources
+        .iter()
+        .map(|source| match source.weight {
+            Some(weight) => format!("'{}' ({:.0}%)", source.name, weight as f64 * 100.0 / weight_sum as f64),
+            None => format!("'{}'", source.name),
+        })
+        .collect();
+    let description: String = format!("{} mix of {}! Seed {}", strategy.label(), listed.join(", "), seed)
+        .chars()
+        .take(MAX_DESCRIPTION_CHARS)
+        .collect();
+    let name = match sources.as_slice() {
+        [first, second] => format!("{} + {}{}", first.name, second.name, defaults.playlist_suffix),
+        _ => format!("{} + {} more{}", sources[0].name, sources.len() - 1, defaults.playlist_suffix),
+    };
+    let created = spotify
+        .create_playlist(&caller, &user_id, &NewPlaylist { name: &name, public: defaults.public, description: &description })
         .await
-    {
-        Ok(response) => {
-            if response.status().is_success(){
-                match response.json::<SpotifyPlaylistTrackResponse>().await {
-                    Ok(page_data) => {
-                        tracing::info!(
-                            "Successfully fetched page of {} tracks. Offset: {}",
-                            page_data.items.len(),
-                            page_data.offset);
-                        Ok(page_data)
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to parse playlist json: {}", e);
-                        Err(ServerFnError::ServerError(format!(
-                            "failed to parse spotify playlists tracks: {}",e
-                        )))
-                    }
-                }
-            } else{
-                let status = response.status();
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown Error".to_string());
-                tracing::error!("failed to get playlist tracks from spotify, status:{}, error:{}", status, error_text);
+        .map_err(AppError::from)?;
+    let mut report = add_in_chunks(&spotify, &caller, &created.id, &track_uris, defaults, AddTracksReport::default()).await?;
+    report.skipped = skipped;
+    tracing::info!("API: Merged {} tracks into '{}' (ID: {})", report.added.len(), name, created.id);
 
-                Err(ServerFnError::ServerError(format!(
-                    "Spotify API Error ({}): {}", status, error_text)))
-            }
+    let web_url = created
+        .external_urls
+        .spotify
+        .unwrap_or_else(|| format!("https://open.spotify.com/playlist/{}", created.id));
+    Ok(NewPlaylistDetails {
+        id: created.id,
+        name: created.name,
+        external_url: web_url,
+        saved_to: ShuffleDestination::NewPlaylist,
+        report,
+        summary,
+        merged_from,
+    })
+}
+
+/// The playlist a shuffle reads from, [`LIKED_SONGS_ID`] giving the user's Liked Songs
+#[cfg(feature="server")]
+async fn source_playlist(spotify: &SpotifyClient, caller: &Caller, playlist_id: &str) -> Result<SpotifyPlaylistItem, SpotifyError>{
+    if playlist_id != LIKED_SONGS_ID {
+        return spotify.playlist(caller, playlist_id).await;
+    }
+    let profile = spotify.me(caller).await?;
+    let total = spotify.saved_track_count(caller).await?;
+    Ok(SpotifyPlaylistItem::liked_songs(
+        SpotifyPlaylistOwner { id: profile.id, display_name: Some(profile.display_name) },
+        total,
+    ))
+}
+
+#[cfg(feature="server")]
+async fn source_items(spotify: &SpotifyClient, caller: &Caller, playlist_id: &str) -> Result<Vec<PlaylistItemTrackWrapper>, SpotifyError>{
+    if playlist_id == LIKED_SONGS_ID {
+        spotify.saved_tracks_all(caller).await
+    } else {
+        spotify.playlist_tracks_all(caller, playlist_id).await
+    }
+}
+
+/// Puts a playlist shuffled in place back in the order it had before, as long as nothing was
+/// added since. Items missing, say after a rewrite that stopped partway, are put back too.
+#[server(UndoInPlaceShuffle)]
+pub async fn undo_in_place_shuffle(playlist_id: String) -> Result<(), ServerFnError<AppError>> {
+    let app_state = app_state().await?;
+    let user_id = get_spotify_user_id().await?;
+    let (spotify, caller) = spotify().await?;
+
+    let Some(previous_order) = app_state
+        .storage
+        .undo_order(&user_id, &playlist_id)
+        .map_err(|e| AppError::internal(format!("couldn't read the undo order: {}", e)))?
+    else {
+        return Err(AppError::validation("There is no in-place shuffle of this playlist to undo.").into());
+    };
+
+    let current_order: Vec<String> = positioned_items(&spotify, &caller, &playlist_id).await?.into_iter().map(|item| item.uri).collect();
+    let mut missing: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+    for uri in &previous_order {
+        *missing.entry(uri.as_str()).or_default() += 1;
+    }
+    for uri in &current_order {
+        match missing.get_mut(uri.as_str()) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => return Err(AppError::validation("Items were added since the shuffle, so the old order can't be restored.").into()),
         }
-        Err(e) => {
-            tracing::error!("Network Error while fetching user playlists: {}",e);
-            Err(ServerFnError::ServerError(format!("Network Error: {}", e)))
+    }
+    missing.retain(|_, count| *count > 0);
+
+    let defaults = &app_state.config.shuffle;
+    let report = if missing.is_empty() {
+        reorder(&spotify, &caller, &playlist_id, &current_order, &previous_order, defaults).await?
+    } else if missing.keys().any(|uri| uri.starts_with("spotify:local:")) {
+        return Err(AppError::validation("Local files were removed since the shuffle, and Spotify doesn't let us add them back.").into());
+    } else {
+        // a rewrite that stopped partway left the playlist short, the saved order has everything
+        tracing::info!("API: Rewriting {} to put back {} missing items", playlist_id, missing.len());
+        replace_in_chunks(&spotify, &caller, &playlist_id, &previous_order, defaults).await?
+    };
+    if let Some(error) = report.stopped_by {
+        return Err(error.into());
+    }
+    if !report.failed.is_empty() {
+        return Err(AppError::SpotifyApi {
+            status: 502,
+            message: format!("Spotify kept rejecting {} of the items being put back, try again.", report.failed.len()),
         }
+        .into());
+    }
+    if let Err(e) = app_state.storage.forget_undo_order(&user_id, &playlist_id) {
+        tracing::error!("API: Failed to forget the undo order of {}: {}", playlist_id, e);
     }
+    tracing::info!("API: Restored the order of {} from before its shuffle", playlist_id);
+    Ok(())
 }
 
-#[server(ShuffleAndSavePlaylist)] // Reverting to this name
-pub async fn shuffle_and_save_new_playlist(
-    original_playlist_id: String,
-    original_playlist_name: String,
-) -> Result<NewPlaylistDetails, ServerFnError> { // Use default ServerFnError for now
-    #[cfg(feature = "server")]
-    {
-        tracing::info!("API: Server-side shuffle for playlist: '{}' (ID: {})", original_playlist_name, original_playlist_id);
-
-        // 1. Get Current User's Spotify ID
-        let user_id = match get_spotify_user_id().await {
-            Ok(id) => id,
-            Err(e) => return Err(ServerFnError::ServerError(format!("Failed to get user ID: {}", e))),
-        };
-        tracing::info!("API: Target user ID: {}", user_id);
+/// Reorders the playlist itself, so it keeps its link and followers, see [`reorder`] for how.
+/// The order before is kept so [`undo_in_place_shuffle`] can restore it.
+#[cfg(feature="server")]
+async fn shuffle_in_place(
+    spotify: &SpotifyClient,
+    caller: &Caller,
+    app_state: &AppState,
+    user_id: &str,
+    playlist: SpotifyPlaylistItem,
+    strategy: &dyn shuffle::ShuffleStrategy,
+    seed: u64,
+) -> Result<NewPlaylistDetails, AppError>{
+    if playlist.is_liked_songs() {
+        return Err(AppError::validation("Spotify doesn't let apps reorder Liked Songs, shuffle it into a copy instead."));
+    }
+    if !playlist.is_owned_by(user_id) {
+        return Err(AppError::validation("Only playlists you own can be shuffled in place, shuffle it into a copy instead."));
+    }
+    let items = positioned_items(spotify, caller, &playlist.id).await?;
+    if items.is_empty() {
+        return Err(AppError::validation(format!("Playlist '{}' is empty.", playlist.name)));
+    }
+    let current_order: Vec<String> = items.iter().map(|item| item.uri.clone()).collect();
 
-        // 2. Fetch All Tracks for the Original Playlist
-        let tracks_for_shuffling = match get_spotify_playlist_tracks_all(original_playlist_id.clone()).await {
-            Ok(tracks) => tracks,
-            Err(e) => return Err(ServerFnError::ServerError(format!("Failed to fetch tracks for '{}': {}", original_playlist_name, e))),
-        };
+    let shuffled = strategy.shuffle(items, &mut shuffle::seeded_rng(seed));
+    let summary = shuffle::summarize(strategy, &shuffled, seed, &playlist.snapshot_id);
+    let new_order: Vec<String> = shuffled.into_iter().map(|item| item.uri).collect();
+    tracing::info!("API: Shuffling {} items of '{}' in place with '{}', seed {}.", new_order.len(), playlist.name, strategy.name(), seed);
 
-        if tracks_for_shuffling.is_empty() {
-            return Err(ServerFnError::ServerError(format!("Playlist '{}' is empty.", original_playlist_name)));
+    // saved first: if a move fails part of the way, undo still knows the order to go back to
+    app_state
+        .storage
+        .save_undo_order(user_id, &playlist.id, &current_order)
+        .map_err(|e| AppError::internal(format!("couldn't save the order to undo to: {}", e)))?;
+    let report = reorder(spotify, caller, &playlist.id, &current_order, &new_order, &app_state.config.shuffle).await?;
+    let web_url = playlist
+        .external_urls
+        .spotify
+        .unwrap_or_else(|| format!("https://open.spotify.com/playlist/{}", playlist.id));
+    Ok(NewPlaylistDetails {
+        id: playlist.id,
+        name: playlist.name,
+        external_url: web_url,
+        saved_to: ShuffleDestination::InPlace,
+        report,
+        summary,
+        merged_from: Vec::new(),
+    })
+}
+
+/// Every item of the playlist at its position. Moves are by position, so an item Spotify won't
+/// describe makes the playlist impossible to reorder safely.
+#[cfg(feature="server")]
+async fn positioned_items(spotify: &SpotifyClient, caller: &Caller, playlist_id: &str) -> Result<Vec<SpotifyTrackItem>, AppError>{
+    spotify
+        .playlist_tracks_all(caller, playlist_id)
+        .await?
+        .into_iter()
+        .map(|item| item.track)
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| AppError::validation("Spotify didn't return every item of this playlist, so it can't be reordered in place."))
+}
+
+/// Above this many moves a reorder rewrites the playlist instead: a random order takes about one
+/// move per item, each its own request, where a rewrite takes one per chunk
+#[cfg(feature="server")]
+const MAX_REORDER_MOVES: usize = 50;
+
+/// Puts the items of a playlist, expected to be in the order `from`, into the order of `to`,
+/// which must hold the same URIs.
+///
+/// Spotify doesn't refuse a move made against an older snapshot, it applies it there and merges
+/// whatever changed since. So the playlist is read again right before the moves, and only
+/// reordered if it still holds `from`; its order is checked once more after the last one, so an
+/// edit made meanwhile, by another app or person, is reported instead of silently scrambled.
+///
+/// Moves keep dates added. Past [`MAX_REORDER_MOVES`] the items are replaced in chunks instead,
+/// which resets them; playlists with local files, which Spotify can't add back, are always moved.
+#[cfg(feature="server")]
+async fn reorder(
+    spotify: &SpotifyClient,
+    caller: &Caller,
+    playlist_id: &str,
+    from: &[String],
+    to: &[String],
+    defaults: &ShuffleDefaults,
+) -> Result<AddTracksReport, AppError>{
+    let mut snapshot_id = spotify.playlist(caller, playlist_id).await?.snapshot_id;
+    let items = positioned_items(spotify, caller, playlist_id).await?;
+    if !items.iter().map(|item| &item.uri).eq(from) {
+        return Err(playlist_changed());
+    }
+
+    let moves = moves_between(from, to);
+    let report = if moves.len() <= MAX_REORDER_MOVES || items.iter().any(|item| item.is_local) {
+        for &(start, length, before) in &moves {
+            snapshot_id = spotify
+                .reorder_tracks(caller, playlist_id, start, length, before, &snapshot_id)
+                .await
+                .map_err(|e| match AppError::from(e) {
+                    // a move Spotify couldn't merge into what changed since the snapshot
+                    AppError::SpotifyApi { status: 409, .. } => playlist_changed(),
+                    other => other,
+                })?;
+        }
+        tracing::info!("API: Reordered {} in {} moves", playlist_id, moves.len());
+        AddTracksReport { added: to.to_vec(), ..Default::default() }
+    } else {
+        // a rewrite can't be merged with an edit made meanwhile the way moves are, it overwrites it
+        if spotify.playlist(caller, playlist_id).await?.snapshot_id != snapshot_id {
+            return Err(playlist_changed());
+        }
+        tracing::info!("API: Rewriting {} rather than making {} moves", playlist_id, moves.len());
+        let report = replace_in_chunks(spotify, caller, playlist_id, to, defaults).await?;
+        if !report.failed.is_empty() || report.stopped_by.is_some() {
+            return restore_after_partial_rewrite(spotify, caller, playlist_id, from, defaults, report).await;
         }
-        tracing::info!("API: Fetched {} tracks for '{}'.", tracks_for_shuffling.len(), original_playlist_name);
+        report
+    };
 
-        // 3. Extract Track URIs AND THEN Shuffle them
-        let mut track_uris: Vec<String> = tracks_for_shuffling
-            .into_iter()
-            .filter_map(|t| t.id.map(|id_val| format!("spotify:track:{}", id_val)))
-            .collect();
+    let reordered = positioned_items(spotify, caller, playlist_id).await?;
+    if !reordered.iter().map(|item| &item.uri).eq(to) {
+        return Err(playlist_changed());
+    }
+    Ok(AddTracksReport { verified_total: Some(reordered.len() as u32), ..report })
+}
 
-        if track_uris.is_empty() {
-            return Err(ServerFnError::ServerError("No valid track URIs found in the playlist.".to_string()));
+/// A rewrite that stopped partway leaves the playlist short of items, so the order it had is
+/// written back. Should that fail too, the partial report comes back and
+/// [`undo_in_place_shuffle`] can still put the saved order back later.
+#[cfg(feature="server")]
+async fn restore_after_partial_rewrite(
+    spotify: &SpotifyClient,
+    caller: &Caller,
+    playlist_id: &str,
+    from: &[String],
+    defaults: &ShuffleDefaults,
+    report: AddTracksReport,
+) -> Result<AddTracksReport, AppError>{
+    tracing::warn!("API: Rewriting {} stopped with {} items left out, putting the old order back", playlist_id, report.failed.len());
+    match replace_in_chunks(spotify, caller, playlist_id, from, defaults).await {
+        Ok(restored) if restored.failed.is_empty() && restored.stopped_by.is_none() => {
+            Err(report.stopped_by.unwrap_or_else(|| AppError::SpotifyApi {
+                status: 502,
+                message: format!(
+                    "Spotify kept rejecting {} items while the playlist was rewritten, so its old order was put back. Try again.",
+                    report.failed.len()
+                ),
+            }))
+        }
+        Ok(_) | Err(_) => {
+            tracing::error!("API: Couldn't put the old order of {} back either", playlist_id);
+            Ok(report)
         }
+    }
+}
 
-        // --- Perform shuffle synchronously here ---
-        { // Create a limited scope for rng if using thread_rng
-            let mut rng = thread_rng(); // Create RNG here
-            track_uris.shuffle(&mut rng); // Shuffle the mutable vector
-        } // rng goes out of scope; if it was thread_rng, its non-Send parts are dropped.
-          // If thread_rng() still gives Send issues due to the outer async fn,
-          // use `let mut rng = rand::rngs::StdRng::from_entropy();`
-        tracing::info!("API: Shuffled {} track URIs.", track_uris.len());
-        // --- End shuffle ---
-
-
-        // 4. Create a New Playlist
-        let access_token = match get_access_token().await { // Get token again for subsequent calls
-            Ok(token) => token,
-            Err(e) => return Err(e),
-        };
-        let client = Client::new();
-        let new_playlist_name = format!("{} - TRUE SHUFFLED", original_playlist_name);
-
-        #[derive(serde::Serialize)]
-        struct CreatePlaylistPayload<'a> { name: &'a str, public: bool, description: String }
-        let create_payload = CreatePlaylistPayload {
-            name: &new_playlist_name,
-            public: false,
-            description: format!(
-                "A true random shuffle of '{}'!",
-                original_playlist_name
-            ),
-        };
-        let create_playlist_url = format!("https://api.spotify.com/v1/users/{}/playlists", user_id);
-        tracing::info!("API: Creating new playlist: {}", new_playlist_name);
-
-        let created_playlist_data: SpotifyPlaylistItem = match client
-            .post(&create_playlist_url)
-            .bearer_auth(access_token.clone())
-            .json(&create_payload)
-            .send().await {
-                // Explicit match for Result<reqwest::Response, reqwest::Error>
-                Ok(response) => {
-                    if response.status().is_success() || response.status().as_u16() == 201 {
-                        match response.json::<SpotifyPlaylistItem>().await {
-                            Ok(data) => data,
-                            Err(e) => return Err(ServerFnError::ServerError(format!("API: Parse new playlist response error: {}", e))),
-                        }
-                    } else {
-                        let s = response.status(); let t = response.text().await.unwrap_or_default();
-                        return Err(ServerFnError::ServerError(format!("API: Spotify error {} creating playlist: {}", s, t)));
-                    }
-                }
-                Err(e) => return Err(ServerFnError::ServerError(format!("API: Network error creating playlist: {}", e))),
+#[cfg(feature="server")]
+fn playlist_changed() -> AppError {
+    AppError::SpotifyApi {
+        status: 409,
+        message: "The playlist changed while it was being reordered, try again.".to_string(),
+    }
+}
+
+/// `(range_start, range_length, insert_before)` moves that turn `from` into `to`, taking runs
+/// already in the right order along in one move. Repeated URIs are interchangeable.
+#[cfg(feature="server")]
+fn moves_between(from: &[String], to: &[String]) -> Vec<(usize, usize, usize)> {
+    let mut current: Vec<&String> = from.iter().collect();
+    let mut moves = Vec::new();
+    let mut position = 0;
+    while position < to.len() {
+        if *current[position] == to[position] {
+            position += 1;
+            continue;
+        }
+        let start = position + current[position..].iter().position(|uri| **uri == to[position]).expect("`to` holds the same URIs as `from`");
+        let length = (0..current.len() - start)
+            .take_while(|offset| position + offset < to.len() && *current[start + offset] == to[position + offset])
+            .count();
+        let run: Vec<&String> = current.drain(start..start + length).collect();
+        current.splice(position..position, run);
+        moves.push((start, length, position));
+        position += length;
+    }
+    moves
+}
+
+/// Tries per chunk before its URIs are given up on
+#[cfg(feature="server")]
+const CHUNK_ATTEMPTS: u32 = 3;
+
+/// Appends `uris` in chunks after what `report` says this shuffle already put in the playlist.
+/// A chunk that errors may still have landed (a POST that timed out, a gateway error after the
+/// fact), so the playlist's length decides whether it is sent again. Errors that every later
+/// chunk would hit too, like an expired login, end the whole add; the report still comes back,
+/// with the error in `stopped_by`, so the playlist made so far isn't lost to the user.
+#[cfg(feature="server")]
+async fn add_in_chunks(
+    spotify: &SpotifyClient,
+    caller: &Caller,
+    playlist_id: &str,
+    uris: &[String],
+    defaults: &ShuffleDefaults,
+    mut report: AddTracksReport,
+) -> Result<AddTracksReport, AppError>{
+
+    for (index, chunk) in uris.chunks(defaults.add_chunk_size).enumerate() {
+        if index > 0 {
+            tokio::time::sleep(tokio::time::Duration::from_millis(defaults.chunk_delay_ms)).await;
+        }
+        tracing::info!("API: Adding {} tracks to new playlist ID {}", chunk.len(), playlist_id);
+
+        let mut attempt = 0;
+        let landed = loop {
+            attempt += 1;
+            let error = match spotify.add_tracks(caller, playlist_id, chunk).await {
+                Ok(_snapshot_id) => break true,
+                Err(e) => AppError::from(e),
             };
-        let new_playlist_id = created_playlist_data.id.clone();
-        tracing::info!("API: New playlist created '{}' (ID: {})", new_playlist_name, new_playlist_id);
-
-        // 5. Add Shuffled Tracks to the New Playlist (in batches)
-        if !track_uris.is_empty() {
-            let add_tracks_url_base = format!("https://api.spotify.com/v1/playlists/{}/tracks", new_playlist_id);
-            for chunk_of_uris in track_uris.chunks(100) {
-                 #[derive(serde::Serialize)]
-                struct AddTracksPayload<'a> {
-                    uris: &'a [String],
-                }
-                let add_payload = AddTracksPayload {
-                    uris: chunk_of_uris,
-                };
-                tracing::info!(
-                    "API: Adding {} tracks to new playlist ID {}",
-                    chunk_of_uris.len(),
-                    new_playlist_id
-                );
-                match client.post(&add_tracks_url_base).bearer_auth(access_token.clone()).json(&add_payload).send().await {
-                    Ok(_response) => { /* Check status */ }
-                    Err(e) => return Err(ServerFnError::ServerError(format!("API: Network error adding tracks: {}",e))),
+            if matches!(error.recovery(), Recovery::LogIn | Recovery::Reconnect) {
+                tracing::error!("API: Stopped adding to playlist ID {} at chunk {}: {}", playlist_id, index, error.message());
+                report.failed.extend_from_slice(&uris[index * defaults.add_chunk_size..]);
+                report.stopped_by = Some(error);
+                return Ok(report);
+            }
+            tracing::warn!("API: Adding chunk {} failed (attempt {}): {}", index, attempt, error.message());
+
+            let expected_before = report.added.len() as u32;
+            match spotify.playlist_track_count(caller, playlist_id).await {
+                Ok(count) if count >= expected_before + chunk.len() as u32 => {
+                    tracing::info!("API: Chunk {} landed despite the error", index);
+                    break true;
                 }
-                if track_uris.len() > 100 && chunk_of_uris.len() == 100 { // Avoid sleep if only one chunk or last small chunk
-                    tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
+                Ok(_) if attempt < CHUNK_ATTEMPTS => continue,
+                Ok(_) => break false,
+                // without the count a retry could add the chunk twice
+                Err(e) => {
+                    tracing::error!("API: Couldn't check whether chunk {} landed: {}", index, e);
+                    break false;
                 }
             }
+        };
+
+        if landed {
+            report.added.extend_from_slice(chunk);
+        } else {
+            report.failed.extend_from_slice(chunk);
         }
-        tracing::info!("API: All tracks added to new playlist: {}", new_playlist_name);
-
-        // 6. Copy the original playlist's image to the new playlist
-        // First, get the original playlist details to access its images
-        match get_spotify_playlist(original_playlist_id.clone()).await {
-            Ok(original_playlist) => {
-                if let Some(images) = &original_playlist.images {
-                    if !images.is_empty() {
-                        // Typically we want the first image (usually the largest)
-                        let original_image_url = &images[0].url;
-                        tracing::info!("API: Copying image from original playlist: {}", original_image_url);
-                        
-                        // Fetch the image from the URL
-                        match client.get(original_image_url).send().await {
-                            Ok(img_response) => {
-                                if img_response.status().is_success() {
-                                    // Get the image bytes
-                                    match img_response.bytes().await {
-                                        Ok(img_bytes) => {
-                                            // Convert to base64 (required by Spotify API)
-                                            let base64_img = base64::encode(img_bytes);
-                                            
-                                            // Call Spotify API to update playlist image
-                                            let upload_image_url = format!("https://api.spotify.com/v1/playlists/{}/images", new_playlist_id);
-                                            
-                                            match client
-                                                .put(&upload_image_url)
-                                                .bearer_auth(access_token.clone())
-                                                .header("Content-Type", "image/jpeg")
-                                                .body(base64_img)
-                                                .send()
-                                                .await
-                                            {
-                                                Ok(upload_response) => {
-                                                    if upload_response.status().is_success() {
-                                                        tracing::info!("API: Successfully copied image to new playlist");
-                                                    } else {
-                                                        let status = upload_response.status();
-                                                        let err_text = upload_response.text().await.unwrap_or_default();
-                                                        tracing::error!("API: Failed to upload image: {} - {}", status, err_text);
-                                                    }
-                                                },
-                                                Err(e) => {
-                                                    tracing::error!("API: Network error uploading image: {}", e);
-                                                }
-                                            }
-                                        },
-                                        Err(e) => {
-                                            tracing::error!("API: Failed to get image bytes: {}", e);
-                                        }
-                                    }
-                                } else {
-                                    tracing::error!("API: Failed to fetch image, status: {}", img_response.status());
-                                }
-                            },
-                            Err(e) => {
-                                tracing::error!("API: Network error fetching image: {}", e);
-                            }
-                        }
-                    } else {
-                        tracing::info!("API: Original playlist has no images");
-                    }
-                } else {
-                    tracing::info!("API: Original playlist has no images array");
-                }
-            },
-            Err(e) => {
-                tracing::error!("API: Failed to get original playlist details: {}", e);
-            }
+    }
+
+    report.verified_total = spotify
+        .playlist_track_count(caller, playlist_id)
+        .await
+        .inspect_err(|e| tracing::error!("API: Couldn't verify the new playlist's length: {}", e))
+        .ok();
+    Ok(report)
+}
+
+/// Replaces the playlist's items with the first chunk of `uris` and appends the rest. Replacing
+/// twice does no harm, so the first chunk is simply sent again; if it never goes through the
+/// playlist is left as it was.
+#[cfg(feature="server")]
+async fn replace_in_chunks(
+    spotify: &SpotifyClient,
+    caller: &Caller,
+    playlist_id: &str,
+    uris: &[String],
+    defaults: &ShuffleDefaults,
+) -> Result<AddTracksReport, AppError>{
+    let (first, rest) = uris.split_at(defaults.add_chunk_size.min(uris.len()));
+    tracing::info!("API: Replacing the items of playlist ID {} with {} tracks", playlist_id, first.len());
+
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let error = match spotify.replace_tracks(caller, playlist_id, first).await {
+            Ok(_snapshot_id) => break,
+            Err(e) => AppError::from(e),
+        };
+        if attempt >= CHUNK_ATTEMPTS || matches!(error.recovery(), Recovery::LogIn | Recovery::Reconnect) {
+            return Err(error);
         }
-        
-        // 7. Return Success
-        let web_url = format!("https://open.spotify.com/playlist/{}", new_playlist_id);
-        Ok(NewPlaylistDetails {
-            id: new_playlist_id,
-            name: new_playlist_name,
-            external_url: web_url,
+        tracing::warn!("API: Replacing the first chunk failed (attempt {}): {}", attempt, error.message());
+    }
+
+    let report = AddTracksReport { added: first.to_vec(), ..Default::default() };
+    add_in_chunks(spotify, caller, playlist_id, rest, defaults, report).await
+}
+
+/// Put in the description of every shuffled copy so it can be found again without the database
+#[cfg(feature="server")]
+fn copy_marker(original_playlist_id: &str) -> String {
+    format!("(from spotify:playlist:{})", original_playlist_id)
+}
+
+/// The user's own playlist that earlier shuffles of `original_playlist_id` went to: the one
+/// remembered for them if they still have it, else one whose description carries the source's
+/// marker. A matching name alone isn't enough, that could be any playlist of theirs.
+#[cfg(feature="server")]
+async fn find_shuffled_copy(
+    spotify: &SpotifyClient,
+    caller: &Caller,
+    app_state: &AppState,
+    user_id: &str,
+    original_playlist_id: &str,
+) -> Result<Option<SpotifyPlaylistItem>, AppError>{
+    let remembered = app_state
+        .storage
+        .shuffled_copy(user_id, original_playlist_id)
+        .map_err(|e| AppError::internal(format!("couldn't look up the shuffled copy: {}", e)))?;
+    // the library rather than the playlist itself: a deleted playlist can still be fetched by ID
+    let mut owned: Vec<SpotifyPlaylistItem> = spotify
+        .playlists(caller)
+        .try_filter(|playlist| futures::future::ready(playlist.is_owned_by(user_id) && playlist.id != original_playlist_id))
+        .try_collect()
+        .await
+        .map_err(AppError::from)?;
+
+    let marker = copy_marker(original_playlist_id);
+    let position = owned
+        .iter()
+        .position(|playlist| Some(&playlist.id) == remembered.as_ref())
+        .or_else(|| owned.iter().position(|playlist| playlist.description.as_deref().is_some_and(|d| d.contains(&marker))));
+    Ok(position.map(|position| owned.swap_remove(position)))
+}
+
+/// Copies the first (usually largest) image of `from` onto `to`
+#[cfg(feature="server")]
+async fn copy_cover(spotify: &SpotifyClient, caller: &Caller, original_playlist: &SpotifyPlaylistItem, to: &str) -> Result<(), SpotifyError>{
+    let Some(image) = original_playlist.images.as_ref().and_then(|images| images.first()) else {
+        tracing::info!("API: Original playlist has no images");
+        return Ok(());
+    };
+    tracing::info!("API: Copying image from original playlist: {}", image.url);
+
+    let image_bytes = spotify.fetch_image(&image.url).await?;
+    spotify.upload_cover(caller, to, &STANDARD.encode(image_bytes)).await?;
+    tracing::info!("API: Successfully copied image to new playlist");
+    Ok(())
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use axum::http::{Method, StatusCode};
+    use crate::test_support::{call_server_fn, log_in, test_app_state, test_config, FakeSpotify, FakeTrack};
+
+    async fn setup() -> (FakeSpotify, AppState, String) {
+        let fake = FakeSpotify::start().await;
+        fake.add_user("alice", "Alice");
+        let app_state = test_app_state(test_config(&fake, "http://127.0.0.1/callback"));
+        let cookie = log_in(&app_state, &fake, "alice");
+        (fake, app_state, cookie)
+    }
+
+    fn uris(tracks: &[FakeTrack]) -> Vec<String> {
+        let mut uris: Vec<String> = tracks.iter().map(|t| t.uri.clone()).collect();
+        uris.sort();
+        uris
+    }
+
+    #[tokio::test]
+    async fn shuffle_copies_every_track_into_a_new_playlist() {
+        let (fake, app_state, cookie) = setup().await;
+        let source = FakeTrack::many("t", 250, 10);
+        let source_id = fake.add_playlist("alice", "Road Trip", source.clone());
+
+        let created = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id.clone(), "Road Trip".to_string(), "uniform".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(created.name, "Road Trip - TRUE SHUFFLED");
+        assert!(created.report.is_complete());
+        assert_eq!(created.report.verified_total, Some(250));
+        assert_eq!(created.summary.strategy, "uniform");
+        let copy = fake.playlist(&created.id).expect("new playlist exists");
+        assert_eq!(copy.owner, "alice");
+        assert_eq!(uris(&copy.tracks), uris(&source));
+        assert_eq!(fake.count("POST", &format!("/v1/playlists/{}/tracks", created.id)), 3);
+        assert_eq!(copy.cover_uploads.len(), 1, "the source cover is copied over");
+        assert_eq!(fake.playlist(&source_id).unwrap().tracks, source, "the source is left alone");
+    }
+
+    #[tokio::test]
+    async fn shuffle_refreshes_an_expired_access_token() {
+        let (fake, app_state, cookie) = setup().await;
+        let source_id = fake.add_playlist("alice", "Focus", FakeTrack::many("t", 5, 2));
+        fake.expire_access_tokens();
+
+        let created = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id, "Focus".to_string(), "uniform".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(fake.playlist(&created.id).unwrap().tracks.len(), 5);
+        assert_eq!(fake.count("POST", "/api/token"), 1);
+    }
+
+    #[tokio::test]
+    async fn an_unavailable_token_endpoint_keeps_the_account_linked() {
+        let (fake, app_state, cookie) = setup().await;
+        let playlist_id = fake.add_playlist("alice", "Mine", FakeTrack::many("t", 3, 1));
+        fake.expire_access_tokens();
+        fake.fail(Method::POST, "/api/token", StatusCode::SERVICE_UNAVAILABLE, 1);
+
+        let result = call_server_fn(&app_state, &cookie, get_spotify_playlist(playlist_id.clone())).await;
+
+        assert!(matches!(result, Err(ServerFnError::WrappedServerError(AppError::Network { .. }))));
+        let playlist = call_server_fn(&app_state, &cookie, get_spotify_playlist(playlist_id)).await.unwrap();
+        assert_eq!(playlist.name, "Mine", "the next call refreshes with the same refresh token");
+        assert_eq!(fake.count("POST", "/api/token"), 2);
+    }
+
+    #[tokio::test]
+    async fn a_revoked_refresh_token_unlinks_the_account() {
+        let (fake, app_state, cookie) = setup().await;
+        let playlist_id = fake.add_playlist("alice", "Mine", FakeTrack::many("t", 3, 1));
+        fake.expire_access_tokens();
+        fake.state.lock().unwrap().refresh_tokens.clear();
+
+        let result = call_server_fn(&app_state, &cookie, get_spotify_playlist(playlist_id.clone())).await;
+
+        assert_eq!(result.unwrap_err(), ServerFnError::WrappedServerError(AppError::TokenExpired));
+        let result = call_server_fn(&app_state, &cookie, get_spotify_playlist(playlist_id)).await;
+        assert_eq!(result.unwrap_err(), ServerFnError::WrappedServerError(AppError::Unauthenticated));
+    }
+
+    #[tokio::test]
+    async fn shuffle_rides_out_rate_limits() {
+        let (fake, app_state, cookie) = setup().await;
+        let source = FakeTrack::many("t", 150, 5);
+        let source_id = fake.add_playlist("alice", "Big", source.clone());
+        fake.rate_limit(Method::GET, &format!("/v1/playlists/{}/tracks", source_id), 0, 2);
+        fake.rate_limit(Method::POST, "/v1/users/alice/playlists", 0, 1);
+
+        let created = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id, "Big".to_string(), "uniform".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(uris(&fake.playlist(&created.id).unwrap().tracks), uris(&source));
+    }
+
+    #[tokio::test]
+    async fn shuffle_keeps_episodes_and_reports_local_files() {
+        let (fake, app_state, cookie) = setup().await;
+        let mut source = FakeTrack::many("t", 5, 2);
+        source.push(FakeTrack::episode("e1", "news"));
+        source.push(FakeTrack::local("Demo", "Band"));
+        source.push(FakeTrack::episode("e2", "news"));
+        let source_id = fake.add_playlist("alice", "Mixed", source.clone());
+
+        let created = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id, "Mixed".to_string(), "uniform".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await
+        .unwrap();
+
+        let local = &source[6];
+        let remote: Vec<FakeTrack> = source.iter().filter(|t| !t.is_local).cloned().collect();
+        assert_eq!(uris(&fake.playlist(&created.id).unwrap().tracks), uris(&remote));
+        assert_eq!(created.report.skipped, vec![local.uri.clone()]);
+        assert_eq!(created.report.added.len(), 7);
+        assert!(created.report.failed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_chunks_are_sent_again() {
+        let (fake, app_state, cookie) = setup().await;
+        let source = FakeTrack::many("t", 250, 10);
+        let source_id = fake.add_playlist("alice", "Road Trip", source.clone());
+        fake.fail(Method::POST, "/v1/playlists/*/tracks", StatusCode::INTERNAL_SERVER_ERROR, 1);
+
+        let created = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id, "Road Trip".to_string(), "uniform".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await
+        .unwrap();
+
+        assert!(created.report.is_complete());
+        assert_eq!(uris(&fake.playlist(&created.id).unwrap().tracks), uris(&source));
+        assert_eq!(fake.count("POST", &format!("/v1/playlists/{}/tracks", created.id)), 4);
+    }
+
+    #[tokio::test]
+    async fn chunks_that_landed_despite_an_error_are_not_added_twice() {
+        let (fake, app_state, cookie) = setup().await;
+        let source = FakeTrack::many("t", 250, 10);
+        let source_id = fake.add_playlist("alice", "Road Trip", source.clone());
+        fake.fail_after_handling(Method::POST, "/v1/playlists/*/tracks", StatusCode::GATEWAY_TIMEOUT, 1);
+
+        let created = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id, "Road Trip".to_string(), "uniform".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await
+        .unwrap();
+
+        assert!(created.report.is_complete());
+        assert_eq!(uris(&fake.playlist(&created.id).unwrap().tracks), uris(&source));
+        assert_eq!(fake.count("POST", &format!("/v1/playlists/{}/tracks", created.id)), 3);
+    }
+
+    #[tokio::test]
+    async fn chunks_that_keep_failing_are_reported() {
+        let (fake, app_state, cookie) = setup().await;
+        let source = FakeTrack::many("t", 250, 10);
+        let source_id = fake.add_playlist("alice", "Road Trip", source.clone());
+        fake.fail(Method::POST, "/v1/playlists/*/tracks", StatusCode::BAD_REQUEST, 3);
+
+        let created = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id, "Road Trip".to_string(), "uniform".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await
+        .unwrap();
+
+        let report = &created.report;
+        assert!(!report.is_complete());
+        assert_eq!((report.added.len(), report.failed.len()), (150, 100));
+        assert_eq!(report.verified_total, Some(150));
+        let mut all: Vec<String> = report.added.iter().chain(&report.failed).cloned().collect();
+        all.sort();
+        assert_eq!(all, uris(&source));
+    }
+
+    #[tokio::test]
+    async fn a_lost_login_still_hands_back_the_playlist_made_so_far() {
+        let (fake, app_state, cookie) = setup().await;
+        let source_id = fake.add_playlist("alice", "Road Trip", FakeTrack::many("t", 250, 10));
+        fake.fail(Method::POST, "/v1/playlists/*/tracks", StatusCode::UNAUTHORIZED, 10);
+
+        let created = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id, "Road Trip".to_string(), "uniform".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await
+        .unwrap();
+
+        let report = &created.report;
+        assert!(!report.is_complete());
+        assert_eq!((report.added.len(), report.failed.len()), (0, 250));
+        assert_eq!(report.stopped_by.as_ref().map(AppError::recovery), Some(Recovery::LogIn));
+        assert!(fake.playlist(&created.id).is_some());
+    }
+
+    #[tokio::test]
+    async fn shuffle_of_an_empty_playlist_fails_without_creating_one() {
+        let (fake, app_state, cookie) = setup().await;
+        let source_id = fake.add_playlist("alice", "Nothing", Vec::new());
+
+        let result = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id, "Nothing".to_string(), "uniform".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await;
+
+        assert_eq!(
+            result.unwrap_err(),
+            ServerFnError::WrappedServerError(AppError::validation("Playlist 'Nothing' is empty."))
+        );
+        assert!(fake.playlists_named("Nothing - TRUE SHUFFLED").is_empty());
+    }
+
+    #[tokio::test]
+    async fn shuffle_surfaces_spotify_server_errors() {
+        let (fake, app_state, cookie) = setup().await;
+        let source_id = fake.add_playlist("alice", "Broken", FakeTrack::many("t", 5, 2));
+        fake.fail(Method::POST, "/v1/users/alice/playlists", StatusCode::BAD_GATEWAY, 10);
+
+        let result = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id, "Broken".to_string(), "uniform".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(ServerFnError::WrappedServerError(AppError::SpotifyApi { status: 502, .. }))
+        ));
+        assert!(fake.playlists_named("Broken - TRUE SHUFFLED").is_empty());
+    }
+
+    #[tokio::test]
+    async fn shuffle_uses_the_chosen_strategy() {
+        let (fake, app_state, cookie) = setup().await;
+        let source = FakeTrack::many("t", 12, 3);
+        let source_id = fake.add_playlist("alice", "Albums", source.clone());
+
+        let strategies = call_server_fn(&app_state, &cookie, get_shuffle_strategies()).await.unwrap();
+        assert!(strategies.iter().any(|s| s.name == "albums"));
+        let created = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id, "Albums".to_string(), "albums".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await
+        .unwrap();
+
+        // FakeTrack::many puts artist i's tracks on album i
+        let albums: Vec<String> = fake.playlist(&created.id).unwrap().tracks.into_iter().map(|t| t.album.0).collect();
+        let mut runs = albums.clone();
+        runs.dedup();
+        assert_eq!(runs.len(), 3, "each album is one block: {:?}", albums);
+        assert!(fake.playlist(&created.id).unwrap().description.starts_with("Album blocks shuffle of 'Albums'! Seed "));
+    }
+
+    #[tokio::test]
+    async fn a_seed_reproduces_the_same_order() {
+        let (fake, app_state, cookie) = setup().await;
+        let source_id = fake.add_playlist("alice", "Seeded", FakeTrack::many("t", 60, 6));
+        let shuffle_with = |strategy: &str, seed| {
+            call_server_fn(
+                &app_state,
+                &cookie,
+                shuffle_and_save_new_playlist(source_id.clone(), "Seeded".to_string(), strategy.to_string(), seed, ShuffleDestination::NewPlaylist),
+            )
+        };
+        let order = |id: &str| -> Vec<String> { fake.playlist(id).unwrap().tracks.into_iter().map(|t| t.uri).collect() };
+
+        let first = shuffle_with("spread", Some(42)).await.unwrap();
+        let again = shuffle_with("spread", Some(42)).await.unwrap();
+        let other = shuffle_with("spread", Some(43)).await.unwrap();
+        let picked = shuffle_with("spread", None).await.unwrap();
+
+        assert_eq!(order(&first.id), order(&again.id));
+        assert_ne!(order(&first.id), order(&other.id));
+        assert_eq!((first.summary.seed, first.summary.source_snapshot_id.clone()), (42, again.summary.source_snapshot_id));
+        assert!(fake.playlist(&first.id).unwrap().description.contains("Seed 42 "));
+        let replayed = shuffle_with("spread", Some(picked.summary.seed)).await.unwrap();
+        assert_eq!(order(&picked.id), order(&replayed.id));
+    }
+
+    async fn shuffle_into_copy(app_state: &AppState, cookie: &str, source_id: &str, name: &str) -> NewPlaylistDetails {
+        call_server_fn(
+            app_state,
+            cookie,
+            shuffle_and_save_new_playlist(source_id.to_string(), name.to_string(), "uniform".to_string(), None, ShuffleDestination::ShuffledCopy),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn reshuffling_replaces_the_items_of_the_earlier_copy() {
+        let (fake, app_state, cookie) = setup().await;
+        let source = FakeTrack::many("t", 250, 10);
+        let source_id = fake.add_playlist("alice", "Gym", source.clone());
+
+        let first = shuffle_into_copy(&app_state, &cookie, &source_id, "Gym").await;
+        let second = shuffle_into_copy(&app_state, &cookie, &source_id, "Gym").await;
+
+        assert_eq!(first.saved_to, ShuffleDestination::NewPlaylist);
+        assert_eq!(second.saved_to, ShuffleDestination::ShuffledCopy);
+        assert_eq!(second.id, first.id);
+        assert_eq!(fake.playlists_named("Gym - TRUE SHUFFLED").len(), 1);
+        let copy = fake.playlist(&second.id).unwrap();
+        assert_eq!(uris(&copy.tracks), uris(&source));
+        assert!(copy.description.contains(&format!("Seed {} ", second.summary.seed)));
+        assert_eq!(fake.count("PUT", &format!("/v1/playlists/{}/tracks", first.id)), 1);
+    }
+
+    #[tokio::test]
+    async fn a_new_playlist_does_not_replace_the_remembered_copy() {
+        let (fake, app_state, cookie) = setup().await;
+        let source_id = fake.add_playlist("alice", "Gym", FakeTrack::many("t", 30, 3));
+        let copy = shuffle_into_copy(&app_state, &cookie, &source_id, "Gym").await;
+
+        let extra = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id.clone(), "Gym".to_string(), "uniform".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await
+        .unwrap();
+        let again = shuffle_into_copy(&app_state, &cookie, &source_id, "Gym").await;
+
+        assert_ne!(extra.id, copy.id);
+        assert_eq!((again.saved_to, again.id), (ShuffleDestination::ShuffledCopy, copy.id));
+    }
+
+    #[tokio::test]
+    async fn copies_made_elsewhere_are_found_by_their_marker() {
+        let (fake, app_state, cookie) = setup().await;
+        let source_id = fake.add_playlist("alice", "Chill", FakeTrack::many("t", 30, 3));
+        let renamed_copy = fake.add_playlist("alice", "Chill (mixed)", FakeTrack::many("old", 5, 1));
+        fake.state.lock().unwrap().playlists.get_mut(&renamed_copy).unwrap().description =
+            format!("Shuffled {}", copy_marker(&source_id));
+
+        let details = shuffle_into_copy(&app_state, &cookie, &source_id, "Chill").await;
+
+        assert_eq!(details.saved_to, ShuffleDestination::ShuffledCopy);
+        assert_eq!(details.id, renamed_copy);
+        assert_eq!(fake.playlist(&renamed_copy).unwrap().tracks.len(), 30);
+    }
+
+    #[tokio::test]
+    async fn a_playlist_with_the_copy_s_name_alone_is_left_alone() {
+        let (fake, app_state, cookie) = setup().await;
+        let source_id = fake.add_playlist("alice", "Chill", FakeTrack::many("t", 30, 3));
+        let lookalike = fake.add_playlist("alice", "Chill - TRUE SHUFFLED", FakeTrack::many("mine", 5, 1));
+
+        let details = shuffle_into_copy(&app_state, &cookie, &source_id, "Chill").await;
+
+        assert_eq!(details.saved_to, ShuffleDestination::NewPlaylist);
+        assert_ne!(details.id, lookalike);
+        assert_eq!(fake.playlist(&lookalike).unwrap().tracks, FakeTrack::many("mine", 5, 1));
+    }
+
+    #[tokio::test]
+    async fn a_deleted_copy_is_not_reused() {
+        let (fake, app_state, cookie) = setup().await;
+        let source_id = fake.add_playlist("alice", "Run", FakeTrack::many("t", 30, 3));
+        let first = shuffle_into_copy(&app_state, &cookie, &source_id, "Run").await;
+        fake.unfollow("alice", &first.id);
+
+        let second = shuffle_into_copy(&app_state, &cookie, &source_id, "Run").await;
+
+        assert_eq!(second.saved_to, ShuffleDestination::NewPlaylist);
+        assert_ne!(second.id, first.id);
+        assert_eq!(fake.playlist(&first.id).unwrap().tracks.len(), 30, "the deleted copy is left alone");
+    }
+
+    #[tokio::test]
+    async fn shuffling_in_place_reorders_the_playlist_and_can_be_undone() {
+        let (fake, app_state, cookie) = setup().await;
+        let mut source = FakeTrack::many("t", 150, 10);
+        source.insert(7, FakeTrack::local("Demo", "Me"));
+        let source_id = fake.add_playlist("alice", "Mine", source.clone());
+        let in_order = |tracks: &[FakeTrack]| -> Vec<String> { tracks.iter().map(|t| t.uri.clone()).collect() };
+
+        let details = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id.clone(), "Mine".to_string(), "uniform".to_string(), Some(7), ShuffleDestination::InPlace),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!((details.id.as_str(), details.saved_to), (source_id.as_str(), ShuffleDestination::InPlace));
+        let shuffled = fake.playlist(&source_id).unwrap().tracks;
+        assert_eq!(uris(&shuffled), uris(&source), "local files stay too");
+        assert_ne!(in_order(&shuffled), in_order(&source));
+        assert_eq!(fake.count("POST", "/v1/users/alice/playlists"), 0);
+        assert_eq!(fake.count("POST", &format!("/v1/playlists/{}/tracks", source_id)), 0, "moved, not rewritten");
+
+        call_server_fn(&app_state, &cookie, undo_in_place_shuffle(source_id.clone())).await.unwrap();
+        assert_eq!(in_order(&fake.playlist(&source_id).unwrap().tracks), in_order(&source));
+        let again = call_server_fn(&app_state, &cookie, undo_in_place_shuffle(source_id)).await.unwrap_err();
+        assert!(matches!(AppError::from_server_fn_error(&again), AppError::Validation { .. }));
+    }
+
+    #[tokio::test]
+    async fn long_playlists_are_rewritten_rather_than_moved_item_by_item() {
+        let (fake, app_state, cookie) = setup().await;
+        let source = FakeTrack::many("t", 150, 10);
+        let source_id = fake.add_playlist("alice", "Long", source.clone());
+        let tracks_path = format!("/v1/playlists/{}/tracks", source_id);
+
+        let details = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id.clone(), "Long".to_string(), "uniform".to_string(), Some(7), ShuffleDestination::InPlace),
+        )
+        .await
+        .unwrap();
+
+        assert!(details.report.is_complete());
+        let shuffled = fake.playlist(&source_id).unwrap().tracks;
+        assert_eq!(uris(&shuffled), uris(&source));
+        assert_ne!(shuffled, source);
+        assert_eq!((fake.count("PUT", &tracks_path), fake.count("POST", &tracks_path)), (1, 1), "one replace, one add");
+
+        call_server_fn(&app_state, &cookie, undo_in_place_shuffle(source_id.clone())).await.unwrap();
+        let restored: Vec<String> = fake.playlist(&source_id).unwrap().tracks.iter().map(|t| t.uri.clone()).collect();
+        assert_eq!(restored, source.iter().map(|t| t.uri.clone()).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn a_rewrite_that_stops_partway_puts_the_old_order_back() {
+        let (fake, app_state, cookie) = setup().await;
+        let source = FakeTrack::many("t", 150, 10);
+        let source_id = fake.add_playlist("alice", "Long", source.clone());
+        // every attempt at the second chunk of the rewrite fails
+        fake.fail(Method::POST, "/v1/playlists/*/tracks", StatusCode::BAD_REQUEST, CHUNK_ATTEMPTS as usize);
+
+        let error = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id.clone(), "Long".to_string(), "uniform".to_string(), Some(7), ShuffleDestination::InPlace),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(AppError::from_server_fn_error(&error), AppError::SpotifyApi { status: 502, .. }));
+        let tracks = fake.playlist(&source_id).unwrap().tracks;
+        assert_eq!(tracks.iter().map(|t| &t.uri).collect::<Vec<_>>(), source.iter().map(|t| &t.uri).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn undo_puts_back_what_a_failed_rewrite_left_out() {
+        let (fake, app_state, cookie) = setup().await;
+        let source = FakeTrack::many("t", 150, 10);
+        let source_id = fake.add_playlist("alice", "Long", source.clone());
+        // the rewrite and the attempt to put the old order back both lose their second chunk
+        fake.fail(Method::POST, "/v1/playlists/*/tracks", StatusCode::BAD_REQUEST, 2 * CHUNK_ATTEMPTS as usize);
+
+        let details = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id.clone(), "Long".to_string(), "uniform".to_string(), Some(7), ShuffleDestination::InPlace),
+        )
+        .await
+        .unwrap();
+        assert_eq!(details.report.failed.len(), 50);
+        assert_eq!(fake.playlist(&source_id).unwrap().tracks.len(), 100);
+
+        call_server_fn(&app_state, &cookie, undo_in_place_shuffle(source_id.clone())).await.unwrap();
+        let tracks = fake.playlist(&source_id).unwrap().tracks;
+        assert_eq!(tracks.iter().map(|t| &t.uri).collect::<Vec<_>>(), source.iter().map(|t| &t.uri).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn only_owned_playlists_are_shuffled_in_place() {
+        let (fake, app_state, cookie) = setup().await;
+        let followed_id = fake.add_playlist("bob", "Bob's", FakeTrack::many("t", 20, 2));
+        fake.state.lock().unwrap().library.entry("alice".to_string()).or_default().push(followed_id.clone());
+
+        let error = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(followed_id.clone(), "Bob's".to_string(), "uniform".to_string(), None, ShuffleDestination::InPlace),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(AppError::from_server_fn_error(&error), AppError::Validation { .. }));
+        assert_eq!(fake.count("PUT", &format!("/v1/playlists/{}/tracks", followed_id)), 0);
+    }
+
+    #[tokio::test]
+    async fn a_reorder_stops_when_the_playlist_changed_meanwhile() {
+        let (fake, app_state, cookie) = setup().await;
+        let source = FakeTrack::many("t", 5, 5);
+        let source_id = fake.add_playlist("alice", "Busy", source.clone());
+        let from: Vec<String> = source.iter().map(|t| t.uri.clone()).collect();
+        let to: Vec<String> = from.iter().rev().cloned().collect();
+        // someone else moves an item after the shuffle read the playlist
+        fake.state.lock().unwrap().playlists.get_mut(&source_id).unwrap().tracks.swap(0, 1);
+        let edited = fake.playlist(&source_id).unwrap().tracks;
+
+        let result = call_server_fn(&app_state, &cookie, async {
+            let (spotify, caller) = spotify().await.unwrap();
+            reorder(&spotify, &caller, &source_id, &from, &to, &app_state.config.shuffle).await
+        })
+        .await;
+
+        assert!(matches!(result, Err(AppError::SpotifyApi { status: 409, .. })));
+        assert_eq!(fake.playlist(&source_id).unwrap().tracks, edited, "nothing was moved");
+        assert_eq!(fake.count("PUT", &format!("/v1/playlists/{}/tracks", source_id)), 0);
+    }
+
+    #[tokio::test]
+    async fn a_rejected_move_is_not_mistaken_for_a_changed_playlist() {
+        let (fake, app_state, cookie) = setup().await;
+        let source = FakeTrack::many("t", 5, 5);
+        let source_id = fake.add_playlist("alice", "Mine", source.clone());
+        let from: Vec<String> = source.iter().map(|t| t.uri.clone()).collect();
+        let to: Vec<String> = from.iter().rev().cloned().collect();
+        fake.fail(Method::PUT, &format!("/v1/playlists/{}/tracks", source_id), StatusCode::BAD_REQUEST, 1);
+
+        let result = call_server_fn(&app_state, &cookie, async {
+            let (spotify, caller) = spotify().await.unwrap();
+            reorder(&spotify, &caller, &source_id, &from, &to, &app_state.config.shuffle).await
         })
+        .await;
+
+        assert!(matches!(result, Err(AppError::SpotifyApi { status: 400, .. })));
+    }
+
+    #[test]
+    fn moves_turn_one_order_into_the_other() {
+        let from: Vec<String> = ["a", "b", "c", "a", "d", "e", "f"].iter().map(|s| s.to_string()).collect();
+        let to: Vec<String> = ["e", "f", "a", "c", "a", "b", "d"].iter().map(|s| s.to_string()).collect();
+
+        let mut order = from.clone();
+        for (start, length, before) in moves_between(&from, &to) {
+            // Spotify's semantics: `insert_before` counts positions before the range is taken out
+            let moved: Vec<String> = order.drain(start..start + length).collect();
+            let at = if before > start { before - length } else { before };
+            order.splice(at..at, moved);
+        }
+
+        assert_eq!(order, to);
+        assert_eq!(moves_between(&from, &to)[0], (5, 2, 0), "runs move together");
+    }
+
+    #[tokio::test]
+    async fn liked_songs_are_listed_first_and_shuffle_like_a_playlist() {
+        let (fake, app_state, cookie) = setup().await;
+        fake.add_playlist("alice", "Road Trip", FakeTrack::many("p", 3, 1));
+        let liked = FakeTrack::many("liked", 120, 8);
+        fake.like("alice", liked.clone());
+
+        let playlists = call_server_fn(&app_state, &cookie, get_spotify_user_playlists_all()).await.unwrap();
+        assert_eq!(playlists.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["Liked Songs", "Road Trip"]);
+        assert_eq!(playlists[0].tracks.total, 120);
+
+        let first = shuffle_into_copy(&app_state, &cookie, LIKED_SONGS_ID, "Liked Songs").await;
+        let second = shuffle_into_copy(&app_state, &cookie, LIKED_SONGS_ID, "Liked Songs").await;
+        assert_eq!(uris(&fake.playlist(&first.id).unwrap().tracks), uris(&liked));
+        assert_eq!((second.id, second.saved_to), (first.id, ShuffleDestination::ShuffledCopy));
+
+        let in_place = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(LIKED_SONGS_ID.to_string(), "Liked Songs".to_string(), "uniform".to_string(), None, ShuffleDestination::InPlace),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(AppError::from_server_fn_error(&in_place), AppError::Validation { .. }));
+    }
+
+    fn merge_source(playlist_id: &str, name: &str, weight: Option<u32>) -> MergeSource {
+        MergeSource { playlist_id: playlist_id.to_string(), name: name.to_string(), weight }
+    }
+
+    #[tokio::test]
+    async fn merging_combines_sources_without_duplicates() {
+        let (fake, app_state, cookie) = setup().await;
+        let a = FakeTrack::many("a", 40, 4);
+        let mut c = FakeTrack::many("c", 15, 3);
+        c.extend(a[..5].iter().cloned());
+        let sources = vec![
+            merge_source(&fake.add_playlist("alice", "A", a.clone()), "A", None),
+            merge_source(&fake.add_playlist("alice", "B", FakeTrack::many("b", 20, 2)), "B", None),
+            merge_source(&fake.add_playlist("alice", "C", c), "C", None),
+        ];
+
+        let merged = call_server_fn(&app_state, &cookie, merge_and_shuffle_playlists(sources.clone(), true, "uniform".to_string(), None))
+            .await
+            .unwrap();
+
+        let playlist = fake.playlist(&merged.id).unwrap();
+        assert_eq!(playlist.tracks.len(), 75);
+        assert_eq!(playlist.name, "A + 2 more - TRUE SHUFFLED");
+        assert!(playlist.description.starts_with("True random mix of 'A', 'B', 'C'! Seed "));
+        assert_eq!(merged.merged_from.iter().map(|c| (c.taken, c.duplicates)).collect::<Vec<_>>(), [(40, 0), (20, 0), (15, 5)]);
+
+        let kept = call_server_fn(&app_state, &cookie, merge_and_shuffle_playlists(sources, false, "uniform".to_string(), None))
+            .await
+            .unwrap();
+        assert_eq!(fake.playlist(&kept.id).unwrap().tracks.len(), 80);
+    }
+
+    #[tokio::test]
+    async fn weighted_merges_take_each_source_s_share() {
+        let (fake, app_state, cookie) = setup().await;
+        let sources = vec![
+            merge_source(&fake.add_playlist("alice", "A", FakeTrack::many("a", 40, 4)), "A", Some(50)),
+            merge_source(&fake.add_playlist("alice", "B", FakeTrack::many("b", 20, 2)), "B", Some(25)),
+            merge_source(&fake.add_playlist("alice", "C", FakeTrack::many("c", 15, 3)), "C", Some(25)),
+        ];
+
+        let merged = call_server_fn(&app_state, &cookie, merge_and_shuffle_playlists(sources.clone(), true, "spread".to_string(), Some(3)))
+            .await
+            .unwrap();
+
+        assert_eq!(merged.merged_from.iter().map(|c| c.taken).collect::<Vec<_>>(), [30, 15, 15]);
+        assert_eq!(fake.playlist(&merged.id).unwrap().tracks.len(), 60);
+        assert!(fake.playlist(&merged.id).unwrap().description.contains("'A' (50%), 'B' (25%), 'C' (25%)"));
+
+        let mut partly_weighted = sources;
+        partly_weighted[2].weight = None;
+        let error = call_server_fn(&app_state, &cookie, merge_and_shuffle_playlists(partly_weighted, true, "spread".to_string(), None))
+            .await
+            .unwrap_err();
+        assert!(matches!(AppError::from_server_fn_error(&error), AppError::Validation { .. }));
+    }
+
+    #[tokio::test]
+    async fn huge_weights_do_not_overflow() {
+        let (fake, app_state, cookie) = setup().await;
+        let sources = vec![
+            merge_source(&fake.add_playlist("alice", "A", FakeTrack::many("a", 10, 2)), "A", Some(u32::MAX)),
+            merge_source(&fake.add_playlist("alice", "B", FakeTrack::many("b", 10, 2)), "B", Some(u32::MAX)),
+        ];
+
+        let merged = call_server_fn(&app_state, &cookie, merge_and_shuffle_playlists(sources, true, "spread".to_string(), Some(3)))
+            .await
+            .unwrap();
+
+        assert_eq!(merged.merged_from.iter().map(|c| c.taken).collect::<Vec<_>>(), [10, 10]);
+        assert!(fake.playlist(&merged.id).unwrap().description.contains("'A' (50%), 'B' (50%)"));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn a_weighted_source_left_empty_by_dedupe_is_named() {
+        let (fake, app_state, cookie) = setup().await;
+        let a = FakeTrack::many("a", 10, 2);
+        let sources = vec![
+            merge_source(&fake.add_playlist("alice", "A", a.clone()), "A", Some(50)),
+            merge_source(&fake.add_playlist("alice", "Subset", a[..4].to_vec()), "Subset", Some(50)),
+        ];
+
+        let error = call_server_fn(&app_state, &cookie, merge_and_shuffle_playlists(sources, true, "spread".to_string(), None))
+            .await
+            .unwrap_err();
+
+        match AppError::from_server_fn_error(&error) {
+            AppError::Validation { message } => assert!(message.contains("'Subset'"), "{}", message),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+        assert_eq!(fake.count("POST", "/v1/users/alice/playlists"), 0);
+    }
+
+    #[tokio::test]
+    async fn shuffle_rejects_an_unknown_strategy() {
+        let (fake, app_state, cookie) = setup().await;
+        let source_id = fake.add_playlist("alice", "Mine", FakeTrack::many("t", 3, 1));
+
+        let result = call_server_fn(
+            &app_state,
+            &cookie,
+            shuffle_and_save_new_playlist(source_id, "Mine".to_string(), "sorted".to_string(), None, ShuffleDestination::NewPlaylist),
+        )
+        .await;
+
+        assert_eq!(
+            result.unwrap_err(),
+            ServerFnError::WrappedServerError(AppError::validation("Unknown shuffle strategy 'sorted'."))
+        );
+        assert_eq!(fake.count("GET", "/v1/me"), 0);
+    }
+
+    #[tokio::test]
+    async fn server_fns_require_a_session() {
+        let (fake, app_state, _) = setup().await;
+        fake.add_playlist("alice", "Mine", FakeTrack::many("t", 3, 1));
+
+        let result = call_server_fn(&app_state, "", get_spotify_user_playlists_all()).await;
+
+        assert_eq!(result.unwrap_err(), ServerFnError::WrappedServerError(AppError::Unauthenticated));
+        assert_eq!(fake.count("GET", "/v1/me/playlists"), 0);
+    }
+
+    #[tokio::test]
+    async fn persistent_rate_limits_tell_the_ui_how_long_to_wait() {
+        let (fake, app_state, cookie) = setup().await;
+        fake.rate_limit(Method::GET, "/v1/me/playlists", 600, 1);
+
+        let result = call_server_fn(&app_state, &cookie, get_spotify_user_playlists_all()).await;
+
+        assert_eq!(
+            result.unwrap_err(),
+            ServerFnError::WrappedServerError(AppError::RateLimited { retry_after: Some(600) })
+        );
+    }
+
+    #[tokio::test]
+    async fn playlists_are_fetched_across_pages() {
+        let (fake, app_state, cookie) = setup().await;
+        for i in 0..120 {
+            fake.add_playlist("alice", &format!("Playlist {}", i), Vec::new());
+        }
+
+        let playlists = call_server_fn(&app_state, &cookie, get_spotify_user_playlists_all()).await.unwrap();
+
+        assert_eq!(playlists.len(), 120);
+        assert!(playlists.iter().all(|p| p.is_owned_by("alice") && p.tracks.total == 0 && !p.snapshot_id.is_empty()));
+        assert_eq!(fake.count("GET", "/v1/me/playlists"), 3);
+    }
+}
@@ -3,11 +3,26 @@ use serde::{Deserialize, Serialize};
 #[derive(Deserialize, Debug)]
 pub struct SpotifyTokenResponse {
     pub access_token: String,
-    token_type: String,
-    scope: String,
-    expires_in: u64,
-    refresh_token: Option<String>,
+    pub scope: String,
+    pub expires_in: u64,
+    pub refresh_token: Option<String>,
 }
+// What the browser is allowed to know about its session, never the token itself
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct AuthStatus {
+    pub logged_in: bool,
+    pub display_name: Option<String>,
+    pub scopes: Vec<String>,
+    pub accounts: Vec<LinkedAccountSummary>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LinkedAccountSummary {
+    pub spotify_id: String,
+    pub display_name: String,
+    pub active: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SpotifyUserProfile {
     pub display_name: String,
@@ -28,46 +43,135 @@ pub struct SpotifyPlaylistItem {
     pub name: String,
     pub images: Option<Vec<SpotifyImageObject>>,
     pub description: Option<String>,
-    pub uri: String, // Add owner, public, collaborative, tracks url etc. if needed
+    pub uri: String,
+    pub owner: SpotifyPlaylistOwner,
+    /// null when Spotify doesn't say, e.g. for some followed playlists
+    #[serde(default)]
+    pub public: Option<bool>,
+    #[serde(default)]
+    pub collaborative: bool,
+    pub snapshot_id: String,
+    pub tracks: SpotifyPlaylistTracksRef,
+    #[serde(default)]
+    pub external_urls: SpotifyExternalUrls,
+}
+
+/// Stands in for a playlist id wherever Liked Songs is picked; real playlist ids are 22 base62 characters
+pub const LIKED_SONGS_ID: &str = "liked-songs";
+/// The OAuth scope reading Liked Songs takes, sessions from before it was asked for lack it
+pub const LIKED_SONGS_SCOPE: &str = "user-library-read";
+
+impl SpotifyPlaylistItem {
+    pub fn is_owned_by(&self, spotify_id: &str) -> bool {
+        self.owner.id == spotify_id
+    }
+
+    /// The owner's Liked Songs dressed up as a playlist, which Spotify doesn't list among them.
+    /// There is no snapshot id for the library, so the count stands in for one.
+    pub fn liked_songs(owner: SpotifyPlaylistOwner, total: u32) -> Self {
+        Self {
+            id: LIKED_SONGS_ID.to_string(),
+            name: "Liked Songs".to_string(),
+            images: None,
+            description: Some("Every song you've liked".to_string()),
+            uri: format!("spotify:user:{}:collection", owner.id),
+            owner,
+            public: Some(false),
+            collaborative: false,
+            snapshot_id: format!("{}-{}", LIKED_SONGS_ID, total),
+            tracks: SpotifyPlaylistTracksRef { href: None, total },
+            external_urls: SpotifyExternalUrls { spotify: Some("https://open.spotify.com/collection/tracks".to_string()) },
+        }
+    }
+
+    pub fn is_liked_songs(&self) -> bool {
+        self.id == LIKED_SONGS_ID
+    }
 }
 
-// For the /me/playlists endpoint top-level response
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub struct SpotifyPlaylistsResponse {
-    pub items: Vec<SpotifyPlaylistItem>,
-    pub href: String,
-    pub limit: u32,
-    pub next: Option<String>,
-    pub offset: u32,
-    pub previous: Option<String>,
+pub struct SpotifyPlaylistOwner {
+    pub id: String,
+    pub display_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpotifyPlaylistTracksRef {
+    #[serde(default)]
+    pub href: Option<String>,
     pub total: u32,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SpotifyExternalUrls {
+    pub spotify: Option<String>,
+}
+
+/// Spotify's paging object. Cursor-paged endpoints (e.g. followed artists) leave out
+/// `offset`/`total`, so those default to 0; `next` is what pagination follows either way.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub struct SpotifyPlaylistTrackResponse {
-    pub items: Vec<PlaylistItemTrackWrapper>,
-    //pub href: String,
+pub struct Paging<T> {
+    pub items: Vec<T>,
     pub limit: u32,
     pub next: Option<String>,
+    #[serde(default)]
     pub offset: u32,
+    #[serde(default)]
     pub previous: Option<String>,
+    #[serde(default)]
     pub total: u32,
 }
 
+// For the /me/playlists endpoint top-level response
+pub type SpotifyPlaylistsResponse = Paging<SpotifyPlaylistItem>;
+
+pub type SpotifyPlaylistTrackResponse = Paging<PlaylistItemTrackWrapper>;
+
+/// One entry of a playlist: the item plus who added it and when.
+/// `added_at`/`added_by` are null on very old playlists.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PlaylistItemTrackWrapper {
+    #[serde(default)]
+    pub added_at: Option<String>,
+    #[serde(default)]
+    pub added_by: Option<SpotifyUserRef>,
+    #[serde(default)]
+    pub is_local: bool,
     pub track: Option<SpotifyTrackItem>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpotifyUserRef {
+    pub id: String,
+}
+
+/// A playlist item, a track or (when asked for with `additional_types`) a podcast episode.
+/// Local files come with `id: null`, no popularity and ids missing on their artists and album.
+/// `is_playable` is only sent when a market is requested, `available_markets` only when none is.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SpotifyTrackItem {
     pub id: Option<String>,
     pub uri: String,
     pub name: String,
-    // pub artists: Vec<SpotifyTrackArtistsSimple>,
-    // pub album: SpotifyTrackAlbumSimple,
-    // pub duration_ms: u32,
-    // pub explicit: bool, 
+    /// `track` or `episode`
+    #[serde(rename = "type", default)]
+    pub item_type: Option<String>,
+    #[serde(default)]
+    pub artists: Vec<SpotifyTrackArtistsSimple>,
+    #[serde(default)]
+    pub album: Option<SpotifyTrackAlbumSimple>,
+    #[serde(default)]
+    pub duration_ms: u32,
+    #[serde(default)]
+    pub explicit: bool,
+    #[serde(default)]
+    pub is_local: bool,
+    #[serde(default)]
+    pub popularity: Option<u32>,
+    #[serde(default)]
+    pub is_playable: Option<bool>,
+    #[serde(default)]
+    pub available_markets: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -75,15 +179,112 @@ pub struct SpotifyTrackAlbumSimple {
     pub id: Option<String>,
     pub name: String,
     pub images: Option<Vec<SpotifyImageObject>>,
+    /// `YYYY`, `YYYY-MM` or `YYYY-MM-DD` depending on `release_date_precision`
+    #[serde(default)]
+    pub release_date: Option<String>,
+    #[serde(default)]
+    pub release_date_precision: Option<String>,
+}
+
+impl SpotifyTrackItem {
+    pub fn is_episode(&self) -> bool {
+        self.item_type.as_deref() == Some("episode")
+    }
 }
+
+impl SpotifyTrackAlbumSimple {
+    pub fn release_year(&self) -> Option<u16> {
+        self.release_date.as_deref()?.get(..4)?.parse().ok()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SpotifyTrackArtistsSimple {
     pub id: Option<String>,
     pub name: String,
 }
+
+/// Where a shuffle is saved
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ShuffleDestination {
+    /// Always a new playlist
+    NewPlaylist,
+    /// The copy earlier shuffles of the source went to, keeping its ID; a new playlist the first time
+    ShuffledCopy,
+    /// The source playlist itself is reordered, only for playlists the user owns
+    InPlace,
+}
+
+/// A playlist going into a merge. Either every source has a `weight`, making it that share of
+/// the mix (relative to the others), or none has and every track is used.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MergeSource {
+    pub playlist_id: String,
+    pub name: String,
+    pub weight: Option<u32>,
+}
+
+/// What one source added to a merge
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MergeContribution {
+    pub name: String,
+    pub taken: u32,
+    /// Tracks left out because an earlier source (or the same one) already had them
+    pub duplicates: u32,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct NewPlaylistDetails {
     pub id: String,
     pub name: String,
     pub external_url: String, // The web URL to the new playlist
+    /// Where the shuffle went: `ShuffledCopy` only when an existing copy was rewritten,
+    /// `NewPlaylist` whenever a playlist was created
+    pub saved_to: ShuffleDestination,
+    pub report: AddTracksReport,
+    pub summary: ShuffleSummary,
+    /// One entry per source of a merge, empty for a single playlist
+    #[serde(default)]
+    pub merged_from: Vec<MergeContribution>,
+}
+
+/// The order the shuffle produced, as measured on the tracks it shuffled
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ShuffleSummary {
+    pub strategy: String,
+    /// Shuffling the same snapshot with this seed and strategy gives the same order
+    pub seed: u64,
+    pub source_snapshot_id: String,
+    /// Closest two tracks by the same artist came, 1 is back to back; `None` if no artist repeats
+    pub min_artist_gap: Option<u32>,
+    pub min_album_gap: Option<u32>,
+}
+
+/// A shuffle strategy as the UI lists it, `name` is what the server fns take
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ShuffleStrategyInfo {
+    pub name: String,
+    pub label: String,
+    pub description: String,
+}
+
+/// What happened to each URI the shuffle tried to put in the new playlist
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct AddTracksReport {
+    pub added: Vec<String>,
+    /// Chunks Spotify kept rejecting after every retry
+    pub failed: Vec<String>,
+    /// Items that can't be added through the API at all
+    pub skipped: Vec<String>,
+    /// Track count Spotify reported for the new playlist once everything was added, if it could be read
+    pub verified_total: Option<u32>,
+    /// What ended the add early, the items that weren't sent by then are in `failed`
+    #[serde(default)]
+    pub stopped_by: Option<crate::app_error::AppError>,
+}
+
+impl AddTracksReport {
+    pub fn is_complete(&self) -> bool {
+        self.stopped_by.is_none() && self.failed.is_empty() && self.skipped.is_empty() && self.verified_total == Some(self.added.len() as u32)
+    }
 }
\ No newline at end of file
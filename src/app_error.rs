@@ -0,0 +1,167 @@
+use std::{fmt, str::FromStr};
+
+use dioxus::prelude::ServerFnError;
+use serde::{Deserialize, Serialize};
+
+/// What went wrong in a server fn, in enough detail for the UI to offer the right way out.
+///
+/// Server fns return `ServerFnError<AppError>`. `Display`/`FromStr` are JSON so the error
+/// survives the trip to the browser intact; use [`AppError::message`] for text meant for people.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind")]
+pub enum AppError {
+    /// No session cookie, or the session is gone
+    Unauthenticated,
+    /// Spotify no longer accepts the account's grant
+    TokenExpired,
+    /// The grant lacks a scope the call needs
+    MissingScope { message: String },
+    RateLimited { retry_after: Option<u64> },
+    NotFound { message: String },
+    SpotifyApi { status: u16, message: String },
+    Network { message: String },
+    /// The request can't be done as asked, retrying won't help
+    Validation { message: String },
+    Internal { message: String },
+}
+
+/// The one thing the UI suggests doing about an error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Recovery {
+    LogIn,
+    /// Log in again so Spotify asks for the missing permission
+    Reconnect,
+    WaitAndRetry { seconds: u64 },
+    Retry,
+    ChooseAnother,
+}
+
+impl AppError {
+    pub fn validation(message: impl Into<String>) -> Self {
+        AppError::Validation { message: message.into() }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        AppError::Internal { message: message.into() }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            AppError::Unauthenticated => "You're not logged in.".to_string(),
+            AppError::TokenExpired => "Your Spotify login has expired.".to_string(),
+            AppError::MissingScope { .. } => "betterd doesn't have Spotify's permission to do that yet.".to_string(),
+            AppError::RateLimited { retry_after: Some(seconds) } => {
+                format!("Spotify is rate limiting us, please wait {} seconds.", seconds)
+            }
+            AppError::RateLimited { retry_after: None } => "Spotify is rate limiting us, please wait a moment.".to_string(),
+            AppError::NotFound { message } => format!("Not found: {}", message),
+            AppError::SpotifyApi { status, message } => format!("Spotify answered {}: {}", status, message),
+            AppError::Network { message } => format!("We couldn't reach Spotify: {}", message),
+            AppError::Validation { message } => message.clone(),
+            AppError::Internal { message } => format!("Something went wrong on our side: {}", message),
+        }
+    }
+
+    pub fn recovery(&self) -> Recovery {
+        match self {
+            AppError::Unauthenticated | AppError::TokenExpired => Recovery::LogIn,
+            AppError::MissingScope { .. } => Recovery::Reconnect,
+            AppError::RateLimited { retry_after } => Recovery::WaitAndRetry { seconds: retry_after.unwrap_or(30) },
+            AppError::NotFound { .. } | AppError::Validation { .. } => Recovery::ChooseAnother,
+            AppError::SpotifyApi { .. } | AppError::Network { .. } | AppError::Internal { .. } => Recovery::Retry,
+        }
+    }
+
+    /// Errors that never reached the server fn body (transport, (de)serialization) don't carry an `AppError`
+    pub fn from_server_fn_error(error: &ServerFnError<AppError>) -> Self {
+        match error {
+            ServerFnError::WrappedServerError(error) => error.clone(),
+            ServerFnError::Request(message) => AppError::Network { message: message.clone() },
+            other => AppError::internal(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+impl FromStr for AppError {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+#[cfg(feature = "server")]
+mod server {
+    use super::AppError;
+    use crate::auth::token::TokenError;
+    use crate::spotify::SpotifyError;
+
+    impl From<TokenError> for AppError {
+        fn from(e: TokenError) -> Self {
+            match e {
+                TokenError::NoSession => AppError::Unauthenticated,
+                TokenError::RateLimited { retry_after } => AppError::RateLimited {
+                    retry_after: retry_after.map(|wait| wait.as_secs()),
+                },
+                TokenError::Rejected { status, body } if status.is_server_error() => AppError::Network {
+                    message: format!("Spotify's token endpoint is unavailable ({}): {}", status, body),
+                },
+                TokenError::NoRefreshToken | TokenError::Rejected { .. } => AppError::TokenExpired,
+                TokenError::Network(e) => AppError::Network { message: e.to_string() },
+                TokenError::Parse(e) => AppError::internal(format!("unreadable token response: {}", e)),
+            }
+        }
+    }
+
+    impl From<SpotifyError> for AppError {
+        fn from(e: SpotifyError) -> Self {
+            match e {
+                SpotifyError::Auth(e) => e.into(),
+                SpotifyError::Network(e) => AppError::Network { message: e.to_string() },
+                SpotifyError::RateLimited { retry_after } => AppError::RateLimited {
+                    retry_after: retry_after.map(|wait| wait.as_secs()),
+                },
+                SpotifyError::Api { status, message } => match status.as_u16() {
+                    401 => AppError::TokenExpired,
+                    // Spotify words it "Insufficient client scope"
+                    403 if message.to_lowercase().contains("scope") => AppError::MissingScope { message },
+                    404 => AppError::NotFound { message },
+                    status => AppError::SpotifyApi { status, message },
+                },
+                // Spotify answered 2xx with something we can't use
+                SpotifyError::Parse(_) | SpotifyError::Pagination(_) => AppError::SpotifyApi {
+                    status: 502,
+                    message: e.to_string(),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survives_the_server_fn_error_round_trip() {
+        use dioxus::prelude::server_fn::error::ServerFnErrorSerde;
+
+        let errors = [
+            AppError::Unauthenticated,
+            AppError::RateLimited { retry_after: Some(7) },
+            AppError::SpotifyApi { status: 500, message: "it's | broken".to_string() },
+        ];
+        for error in errors {
+            let wire = ServerFnError::WrappedServerError(error.clone()).ser().unwrap();
+            let back = ServerFnError::<AppError>::de(&wire);
+            assert_eq!(AppError::from_server_fn_error(&back), error);
+        }
+    }
+}
@@ -19,4 +19,7 @@ pub mod pkce{
         let hash = hasher.finalize();
         URL_SAFE_NO_PAD.encode(hash)
     }
-}
\ No newline at end of file
+}
+pub mod pending;
+pub mod session;
+pub mod token;
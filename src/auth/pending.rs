@@ -0,0 +1,151 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+/// How long a user has between hitting /auth/login and Spotify redirecting back
+pub const PENDING_LOGIN_TTL: Duration = Duration::from_secs(10 * 60);
+/// Outstanding logins we are willing to remember at once
+pub const MAX_PENDING_LOGINS: usize = 1024;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct PendingLogin {
+    code_verifier: String,
+    created_at: Instant,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PendingLoginError {
+    Unknown,
+    Expired,
+}
+
+/// PKCE verifiers waiting for their callback, keyed by the OAuth `state`.
+///
+/// Expired entries linger for one more TTL before the sweeper drops them, so a
+/// late callback gets told its login expired instead of looking like a forged state.
+#[derive(Clone)]
+pub struct PendingLogins {
+    entries: Arc<Mutex<HashMap<String, PendingLogin>>>,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+impl PendingLogins {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+            max_entries,
+        }
+    }
+
+    pub fn insert(&self, state: String, code_verifier: String) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() >= self.max_entries {
+            entries.retain(|_, pending| pending.created_at.elapsed() < self.ttl);
+        }
+        // still full of live logins, make room by forgetting the oldest one
+        while entries.len() >= self.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, pending)| pending.created_at)
+                .map(|(state, _)| state.clone());
+            match oldest {
+                Some(oldest) => {
+                    tracing::warn!("Too many pending logins, evicting the oldest");
+                    entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+
+        entries.insert(state, PendingLogin { code_verifier, created_at: Instant::now() });
+    }
+
+    /// Removes the entry for `state` and hands back its verifier if it hasn't expired
+    pub fn take(&self, state: &str) -> Result<String, PendingLoginError> {
+        let pending = self
+            .entries
+            .lock()
+            .unwrap()
+            .remove(state)
+            .ok_or(PendingLoginError::Unknown)?;
+
+        if pending.created_at.elapsed() >= self.ttl {
+            return Err(PendingLoginError::Expired);
+        }
+        Ok(pending.code_verifier)
+    }
+
+    /// Drops entries that expired more than a TTL ago, returns how many went
+    pub fn sweep(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|_, pending| pending.created_at.elapsed() < self.ttl * 2);
+        before - entries.len()
+    }
+
+    pub fn spawn_sweeper(&self) -> tokio::task::JoinHandle<()> {
+        let pending = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                let swept = pending.sweep();
+                if swept > 0 {
+                    tracing::info!("Swept {} abandoned logins", swept);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+
+    const SHORT_TTL: Duration = Duration::from_millis(20);
+
+    #[test]
+    fn a_verifier_is_handed_out_once() {
+        let pending = PendingLogins::new(PENDING_LOGIN_TTL, MAX_PENDING_LOGINS);
+        pending.insert("state".to_string(), "verifier".to_string());
+
+        assert_eq!(pending.take("state"), Ok("verifier".to_string()));
+        assert_eq!(pending.take("state"), Err(PendingLoginError::Unknown));
+    }
+
+    #[test]
+    fn an_old_login_is_expired() {
+        let pending = PendingLogins::new(SHORT_TTL, MAX_PENDING_LOGINS);
+        pending.insert("state".to_string(), "verifier".to_string());
+        sleep(SHORT_TTL + Duration::from_millis(10));
+
+        assert_eq!(pending.take("state"), Err(PendingLoginError::Expired));
+    }
+
+    #[test]
+    fn the_oldest_login_is_evicted_at_the_cap() {
+        let pending = PendingLogins::new(PENDING_LOGIN_TTL, 2);
+        for state in ["first", "second", "third"] {
+            pending.insert(state.to_string(), format!("{}-verifier", state));
+            sleep(Duration::from_millis(2));
+        }
+
+        assert_eq!(pending.take("first"), Err(PendingLoginError::Unknown));
+        assert_eq!(pending.take("second"), Ok("second-verifier".to_string()));
+        assert_eq!(pending.take("third"), Ok("third-verifier".to_string()));
+    }
+
+    #[test]
+    fn sweep_drops_logins_expired_a_ttl_ago() {
+        let pending = PendingLogins::new(SHORT_TTL, MAX_PENDING_LOGINS);
+        pending.insert("abandoned".to_string(), "verifier".to_string());
+        sleep(SHORT_TTL * 2 + Duration::from_millis(10));
+        pending.insert("fresh".to_string(), "verifier".to_string());
+
+        assert_eq!(pending.sweep(), 1);
+        assert_eq!(pending.take("abandoned"), Err(PendingLoginError::Unknown));
+        assert_eq!(pending.take("fresh"), Ok("verifier".to_string()));
+    }
+}
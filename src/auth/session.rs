@@ -0,0 +1,331 @@
+use std::{collections::HashMap, sync::{Arc, RwLock}};
+
+use axum::http::{header::COOKIE, HeaderMap};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, thread_rng, Rng, RngCore};
+use sha2::Sha256;
+
+use crate::auth::token::SessionTokens;
+use crate::storage::{Storage, StoredUser};
+
+pub const SESSION_COOKIE: &str = "bs_session";
+/// Ties an OAuth `state` to the browser that started the login, one cookie per state so
+/// logins started in several tabs don't overwrite each other
+pub const LOGIN_STATE_COOKIE_PREFIX: &str = "bs_login_";
+const SESSION_ID_LEN: usize = 32;
+const SESSION_MAX_AGE_SECS: u64 = 60 * 60 * 24 * 30;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// One Spotify account linked to a session, with its own grant
+pub struct LinkedAccount {
+    pub user: StoredUser,
+    pub tokens: SessionTokens,
+    refresh_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl LinkedAccount {
+    fn new(user: StoredUser, tokens: SessionTokens) -> Self {
+        Self { user, tokens, refresh_lock: Arc::new(tokio::sync::Mutex::new(())) }
+    }
+}
+
+/// A browser session. Every login from the same browser links another account,
+/// the most recent one is active and is what the API calls act as.
+pub struct Session {
+    pub accounts: Vec<LinkedAccount>,
+    pub active_user_id: i64,
+}
+
+impl Session {
+    fn account(&self, user_id: i64) -> Option<&LinkedAccount> {
+        self.accounts.iter().find(|account| account.user.id == user_id)
+    }
+
+    fn account_mut(&mut self, user_id: i64) -> Option<&mut LinkedAccount> {
+        self.accounts.iter_mut().find(|account| account.user.id == user_id)
+    }
+}
+
+/// Server side session table. The browser only ever holds `<id>.<signature>`,
+/// the tokens themselves stay in here. Refresh tokens and session rows are
+/// written through to storage so a restart doesn't log everybody out.
+#[derive(Clone)]
+pub struct SessionStore {
+    secret: Arc<Vec<u8>>,
+    storage: Storage,
+    sessions: Arc<RwLock<HashMap<String, Session>>>,
+}
+
+impl SessionStore {
+    pub fn new(secret: Vec<u8>, storage: Storage) -> Self {
+        Self {
+            secret: Arc::new(secret),
+            storage,
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Loads persisted sessions. Their access tokens are unknown, so the first
+    /// request on each account goes through a refresh.
+    pub fn restore(&self) -> anyhow::Result<usize> {
+        let stored = self.storage.load_sessions()?;
+        let count = stored.len();
+
+        let mut sessions = self.sessions.write().unwrap();
+        for session in stored {
+            let accounts: Vec<LinkedAccount> = session
+                .accounts
+                .into_iter()
+                .map(|account| {
+                    LinkedAccount::new(account.user, SessionTokens::restored(account.refresh_token, account.scope))
+                })
+                .collect();
+            // the active account may be the one we couldn't decrypt
+            let active_user_id = if accounts.iter().any(|a| a.user.id == session.active_user_id) {
+                session.active_user_id
+            } else {
+                accounts[0].user.id
+            };
+            sessions.insert(session.session_id, Session { accounts, active_user_id });
+        }
+        Ok(count)
+    }
+
+    /// Random signing key, sessions will not survive a restart with this
+    pub fn random_secret() -> Vec<u8> {
+        let mut secret = vec![0u8; 32];
+        thread_rng().fill_bytes(&mut secret);
+        secret
+    }
+
+    /// Links a freshly authorized account to the browser's existing session, or starts
+    /// a new session if there is none. Either way the account becomes the active one.
+    pub fn login(&self, existing_session: Option<String>, user: StoredUser, tokens: SessionTokens) -> anyhow::Result<String> {
+        let session_id = existing_session
+            .filter(|session_id| self.sessions.read().unwrap().contains_key(session_id))
+            .unwrap_or_else(|| {
+                thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(SESSION_ID_LEN)
+                    .map(char::from)
+                    .collect()
+            });
+
+        if let Some(refresh_token) = &tokens.refresh_token {
+            self.storage.link_account(&session_id, user.id, refresh_token, &tokens.scope)?;
+        }
+
+        let mut sessions = self.sessions.write().unwrap();
+        let session = sessions
+            .entry(session_id.clone())
+            .or_insert_with(|| Session { accounts: Vec::new(), active_user_id: user.id });
+
+        session.active_user_id = user.id;
+        match session.account_mut(user.id) {
+            Some(account) => {
+                account.user = user;
+                account.tokens = tokens;
+            }
+            None => session.accounts.push(LinkedAccount::new(user, tokens)),
+        }
+        Ok(session_id)
+    }
+
+    /// Logs every account out of the session
+    pub fn remove(&self, session_id: &str) -> Option<Session> {
+        if let Err(e) = self.storage.delete_session(session_id) {
+            tracing::error!("Failed to delete stored session: {}", e);
+        }
+        self.sessions.write().unwrap().remove(session_id)
+    }
+
+    /// Logs a single account out. Returns whether the session still has accounts left,
+    /// the session itself is dropped once the last one goes.
+    pub fn remove_account(&self, session_id: &str, user_id: i64) -> bool {
+        let mut sessions = self.sessions.write().unwrap();
+        let Some(session) = sessions.get_mut(session_id) else {
+            return false;
+        };
+
+        session.accounts.retain(|account| account.user.id != user_id);
+        if session.accounts.is_empty() {
+            sessions.remove(session_id);
+            drop(sessions);
+            if let Err(e) = self.storage.delete_session(session_id) {
+                tracing::error!("Failed to delete stored session: {}", e);
+            }
+            return false;
+        }
+
+        if session.active_user_id == user_id {
+            session.active_user_id = session.accounts[0].user.id;
+            if let Err(e) = self.storage.set_active_account(session_id, session.active_user_id) {
+                tracing::error!("Failed to switch active account: {}", e);
+            }
+        }
+        if let Err(e) = self.storage.unlink_account(session_id, user_id) {
+            tracing::error!("Failed to unlink stored account: {}", e);
+        }
+        true
+    }
+
+    pub fn active_user_id(&self, session_id: &str) -> Option<i64> {
+        self.sessions.read().unwrap().get(session_id).map(|session| session.active_user_id)
+    }
+
+    pub fn tokens(&self, session_id: &str, user_id: i64) -> Option<SessionTokens> {
+        self.sessions
+            .read()
+            .unwrap()
+            .get(session_id)
+            .and_then(|session| session.account(user_id))
+            .map(|account| account.tokens.clone())
+    }
+
+    /// The active account, everything linked to the session and the active account's scopes
+    pub fn status(&self, session_id: &str) -> Option<(StoredUser, Vec<StoredUser>, String)> {
+        let sessions = self.sessions.read().unwrap();
+        let session = sessions.get(session_id)?;
+        let active = session.account(session.active_user_id)?;
+
+        Some((
+            active.user.clone(),
+            session.accounts.iter().map(|account| account.user.clone()).collect(),
+            active.tokens.scope.clone(),
+        ))
+    }
+
+    pub fn update_tokens(&self, session_id: &str, user_id: i64, tokens: SessionTokens) {
+        let mut sessions = self.sessions.write().unwrap();
+        let Some(account) = sessions.get_mut(session_id).and_then(|session| session.account_mut(user_id)) else {
+            return;
+        };
+
+        if tokens.refresh_token != account.tokens.refresh_token || tokens.scope != account.tokens.scope {
+            if let Some(refresh_token) = &tokens.refresh_token {
+                if let Err(e) = self.storage.save_refresh_token(session_id, user_id, refresh_token, &tokens.scope) {
+                    tracing::error!("Failed to persist rotated refresh token: {}", e);
+                }
+            }
+        }
+        account.tokens = tokens;
+    }
+
+    /// Held while refreshing so concurrent requests on one account share a single refresh
+    pub fn refresh_lock(&self, session_id: &str, user_id: i64) -> Option<Arc<tokio::sync::Mutex<()>>> {
+        self.sessions
+            .read()
+            .unwrap()
+            .get(session_id)
+            .and_then(|session| session.account(user_id))
+            .map(|account| account.refresh_lock.clone())
+    }
+
+    /// Builds the Set-Cookie value for a freshly created session
+    pub fn cookie_for(&self, session_id: &str, secure: bool) -> String {
+        let mut cookie = format!(
+            "{}={}.{}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}",
+            SESSION_COOKIE,
+            session_id,
+            self.sign(session_id),
+            SESSION_MAX_AGE_SECS
+        );
+        if secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+
+    /// Set-Cookie value that makes the browser forget its session
+    pub fn expired_cookie(&self, secure: bool) -> String {
+        let mut cookie = format!("{}=; Path=/; HttpOnly; SameSite=Lax; Max-Age=0", SESSION_COOKIE);
+        if secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+
+    /// Pulls the session id out of the request cookies, only if the signature checks out
+    pub fn session_id_from_headers(&self, headers: &HeaderMap) -> Option<String> {
+        cookie_value(headers, SESSION_COOKIE).and_then(|value| self.verify(&value))
+    }
+
+    /// Set-Cookie value that only the browser starting the login for `state` will carry back.
+    /// It holds a keyed hash rather than the state itself, which also travels in the URL.
+    pub fn login_state_cookie(&self, state: &str, max_age_secs: u64, secure: bool) -> String {
+        let mut cookie = format!(
+            "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}",
+            login_state_cookie_name(state),
+            self.sign(&login_state_message(state)),
+            max_age_secs
+        );
+        if secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+
+    /// Set-Cookie value that drops the login state once the callback used it
+    pub fn expired_login_state_cookie(&self, state: &str, secure: bool) -> String {
+        let mut cookie = format!("{}=; Path=/; HttpOnly; SameSite=Lax; Max-Age=0", login_state_cookie_name(state));
+        if secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+
+    /// Whether the request comes from the browser that was sent to Spotify with `state`
+    pub fn login_state_matches(&self, headers: &HeaderMap, state: &str) -> bool {
+        let Some(signature) = cookie_value(headers, &login_state_cookie_name(state))
+            .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+        else {
+            return false;
+        };
+        let mut mac = self.mac();
+        mac.update(login_state_message(state).as_bytes());
+        mac.verify_slice(&signature).is_ok()
+    }
+
+    fn sign(&self, session_id: &str) -> String {
+        let mut mac = self.mac();
+        mac.update(session_id.as_bytes());
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    }
+
+    fn verify(&self, cookie_value: &str) -> Option<String> {
+        let (session_id, signature) = cookie_value.split_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+        let mut mac = self.mac();
+        mac.update(session_id.as_bytes());
+        mac.verify_slice(&signature).ok()?;
+        Some(session_id.to_string())
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.secret).expect("hmac accepts keys of any length")
+    }
+}
+
+fn cookie_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(cookie, _)| *cookie == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// States are alphanumeric, so they can go in a cookie name as they are
+fn login_state_cookie_name(state: &str) -> String {
+    format!("{}{}", LOGIN_STATE_COOKIE_PREFIX, state)
+}
+
+/// Keeps a state's signature from ever doubling as a session signature
+fn login_state_message(state: &str) -> String {
+    format!("login-state:{}", state)
+}
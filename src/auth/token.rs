@@ -0,0 +1,185 @@
+use std::{fmt, time::{Duration, Instant}};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+
+use crate::api_models::SpotifyTokenResponse;
+use crate::server::AppState;
+use crate::spotify::{retry::retry_after, SpotifyClient};
+
+/// Refresh this long before Spotify would start rejecting the token
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+pub struct SessionTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub scope: String,
+    pub expires_at: Instant,
+}
+
+impl SessionTokens {
+    /// Spotify only sometimes rotates the refresh token, so keep the old one if none came back
+    pub fn from_response(response: SpotifyTokenResponse, previous_refresh_token: Option<String>) -> Self {
+        Self {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token.or(previous_refresh_token),
+            scope: response.scope,
+            expires_at: Instant::now() + Duration::from_secs(response.expires_in),
+        }
+    }
+
+    /// Tokens loaded from storage, only the refresh token survives a restart
+    pub fn restored(refresh_token: String, scope: String) -> Self {
+        Self {
+            access_token: String::new(),
+            refresh_token: Some(refresh_token),
+            scope,
+            expires_at: Instant::now(),
+        }
+    }
+
+    pub fn needs_refresh(&self) -> bool {
+        Instant::now() + REFRESH_MARGIN >= self.expires_at
+    }
+}
+
+#[derive(Debug)]
+pub enum TokenError {
+    NoSession,
+    NoRefreshToken,
+    Network(reqwest::Error),
+    /// Spotify asked us to slow down, the grant itself is still good
+    RateLimited { retry_after: Option<Duration> },
+    Rejected { status: reqwest::StatusCode, body: String },
+    Parse(reqwest::Error),
+}
+
+impl TokenError {
+    /// Whether Spotify refused the grant for good, as opposed to failing to answer it.
+    ///
+    /// Only a revoked or unknown refresh token (`invalid_grant`) or credentials Spotify no
+    /// longer accepts (`invalid_client`) mean the account has to be linked again.
+    pub fn revokes_grant(&self) -> bool {
+        let TokenError::Rejected { status, body } = self else {
+            return false;
+        };
+        let error = serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|body| body.get("error")?.as_str().map(str::to_string));
+        matches!(
+            (status.as_u16(), error.as_deref()),
+            (400, Some("invalid_grant")) | (401, Some("invalid_client"))
+        )
+    }
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::NoSession => write!(f, "User not authenticated"),
+            TokenError::NoRefreshToken => write!(f, "Session has no refresh token, please log in again"),
+            TokenError::Network(e) => write!(f, "Network error talking to the token endpoint: {}", e),
+            TokenError::RateLimited { retry_after } => match retry_after {
+                Some(wait) => write!(f, "Token endpoint rate limited, retry after {}s", wait.as_secs()),
+                None => write!(f, "Token endpoint rate limited"),
+            },
+            TokenError::Rejected { status, body } => write!(f, "Token request failed with status {}: {}", status, body),
+            TokenError::Parse(e) => write!(f, "Failed to parse token response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// POSTs a grant to Spotify's token endpoint with the app's client credentials
+pub async fn request_token(client: &SpotifyClient, grant: &[(&str, &str)]) -> Result<SpotifyTokenResponse, TokenError> {
+    let spotify = client.config();
+
+    let mut params = grant.to_vec();
+    params.push(("client_id", &spotify.client_id));
+
+    let auth_header_value = format!(
+        "Basic {}",
+        STANDARD.encode(format!("{}:{}", spotify.client_id, spotify.client_secret))
+    );
+
+    let response = client
+        .http()
+        .post(spotify.accounts_url("api/token"))
+        .header(AUTHORIZATION, auth_header_value)
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .header(ACCEPT, "application/json")
+        .form(&params)
+        .send()
+        .await
+        .map_err(TokenError::Network)?;
+
+    if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return Err(TokenError::RateLimited { retry_after: retry_after(response.headers()) });
+    }
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_else(|_| "Failed to read error body".to_string());
+        return Err(TokenError::Rejected { status, body });
+    }
+
+    response.json::<SpotifyTokenResponse>().await.map_err(TokenError::Parse)
+}
+
+/// Returns a usable access token for the session's active account, refreshing it first
+/// if it is about to expire
+pub async fn access_token(app_state: &AppState, session_id: &str) -> Result<String, TokenError> {
+    let sessions = &app_state.sessions;
+    let user_id = sessions.active_user_id(session_id).ok_or(TokenError::NoSession)?;
+    let tokens = sessions.tokens(session_id, user_id).ok_or(TokenError::NoSession)?;
+    if !tokens.needs_refresh() {
+        return Ok(tokens.access_token);
+    }
+    refresh_session(app_state, session_id, &tokens.access_token).await
+}
+
+/// Swaps the active account's refresh token for a new access token.
+///
+/// Callers pass the token they saw fail (or expire). Refreshes are serialized per session,
+/// so whoever waited on the lock just picks up the token the first caller fetched.
+pub async fn refresh_session(
+    app_state: &AppState,
+    session_id: &str,
+    stale_access_token: &str,
+) -> Result<String, TokenError> {
+    let sessions = &app_state.sessions;
+    let user_id = sessions.active_user_id(session_id).ok_or(TokenError::NoSession)?;
+    let refresh_lock = sessions.refresh_lock(session_id, user_id).ok_or(TokenError::NoSession)?;
+    let _guard = refresh_lock.lock().await;
+
+    let current = sessions.tokens(session_id, user_id).ok_or(TokenError::NoSession)?;
+    if current.access_token != stale_access_token && !current.needs_refresh() {
+        return Ok(current.access_token);
+    }
+
+    let refresh_token = current.refresh_token.clone().ok_or(TokenError::NoRefreshToken)?;
+    tracing::info!("Refreshing access token for session");
+
+    let response = match request_token(&app_state.spotify, &[
+        ("grant_type", "refresh_token"),
+        ("refresh_token", &refresh_token),
+    ])
+    .await
+    {
+        Ok(response) => response,
+        Err(e) if e.revokes_grant() => {
+            // the refresh token was revoked or is invalid, nothing left to do with this account
+            tracing::warn!("Spotify rejected refresh token ({}), unlinking account", e);
+            sessions.remove_account(session_id, user_id);
+            return Err(e);
+        }
+        // rate limits and outages on Spotify's side pass, keep the account for the next try
+        Err(e) => return Err(e),
+    };
+
+    let fresh = SessionTokens::from_response(response, Some(refresh_token));
+    let access_token = fresh.access_token.clone();
+    sessions.update_tokens(session_id, user_id, fresh);
+    Ok(access_token)
+}
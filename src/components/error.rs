@@ -0,0 +1,39 @@
+use dioxus::prelude::*;
+use crate::app_error::{AppError, Recovery};
+use crate::Route;
+
+/// Explains a failed server fn and offers the recovery its [`AppError`] calls for.
+/// Without `on_retry` the retry style recoveries just show their message.
+#[component]
+pub fn ErrorView(error: AppError, on_retry: Option<EventHandler<()>>) -> Element {
+    let button_class = "px-6 py-2 text-white bg-orange-500 rounded-lg hover:bg-orange-600 shadow";
+
+    rsx! {
+        div { class: "text-center p-4",
+            p { class: "text-red-400 mb-4", "{error.message()}" }
+            match error.recovery() {
+                // plain links: the login flow is served by Axum, not the router
+                Recovery::LogIn => rsx! {
+                    a { href: "/auth/login", class: button_class, "Log in again" }
+                },
+                Recovery::Reconnect => rsx! {
+                    a { href: "/auth/login", class: button_class, "Reconnect Spotify" }
+                },
+                Recovery::WaitAndRetry { seconds } => rsx! {
+                    if let Some(on_retry) = on_retry {
+                        p { class: "text-sm text-gray-400 mb-4", "Give it {seconds} seconds before trying again." }
+                        button { class: button_class, onclick: move |_| on_retry.call(()), "Try Again" }
+                    }
+                },
+                Recovery::Retry => rsx! {
+                    if let Some(on_retry) = on_retry {
+                        button { class: button_class, onclick: move |_| on_retry.call(()), "Try Again" }
+                    }
+                },
+                Recovery::ChooseAnother => rsx! {
+                    Link { to: Route::ShufflePage {}, class: button_class, "Pick another playlist" }
+                },
+            }
+        }
+    }
+}
@@ -1,10 +1,10 @@
 use dioxus::prelude::*;
-use crate::{api::get_access_token, Route};
+use crate::{api::get_auth_status, api_models::AuthStatus, Route};
 
 #[component]
 pub fn NavBar() -> Element {
-    let has_token = use_server_future( || async {
-        get_access_token().await})?;
+    let auth_status = use_server_future( || async {
+        get_auth_status().await})?;
 
     rsx! {
         header {
@@ -19,9 +19,14 @@ pub fn NavBar() -> Element {
 
                     li { Link { to: Route::Home {}, class: "hover:text-green-400", "Home" } }
 
-                    match has_token.read().as_ref(){
-                        Some(Err(_e)) =>rsx!{li {Link {to:Route::LoginPage {  }, "Login"}} },
-                        _ => rsx!{li {Link {to:Route::ShufflePage{  }, "Shuffle"}}}
+                    match auth_status.read().as_ref(){
+                        Some(Ok(status)) if !status.logged_in =>rsx!{li {Link {to:Route::LoginPage { error: String::new() }, "Login"}} },
+                        Some(Err(_e)) =>rsx!{li {Link {to:Route::LoginPage { error: String::new() }, "Login"}} },
+                        Some(Ok(status)) => rsx!{
+                            li {Link {to:Route::ShufflePage{  }, "Shuffle"}}
+                            AccountMenu { status: status.clone() }
+                        },
+                        None => rsx!{li {Link {to:Route::ShufflePage{  }, "Shuffle"}}}
                     }
                 }
             }
@@ -37,6 +42,35 @@ pub fn NavBar() -> Element {
     }
 }
 
+// Plain forms so logging out works as a normal POST to the Axum /logout route
+#[component]
+fn AccountMenu(status: AuthStatus) -> Element {
+    rsx! {
+        if status.accounts.len() > 1 {
+            for account in status.accounts.iter() {
+                li { key: "{account.spotify_id}",
+                    form { action: "/logout", method: "post",
+                        input { r#type: "hidden", name: "account", value: "{account.spotify_id}" }
+                        button {
+                            r#type: "submit",
+                            class: if account.active { "text-green-400 hover:text-red-400" } else { "hover:text-red-400" },
+                            "Log out {account.display_name}"
+                        }
+                    }
+                }
+            }
+        }
+        li { a { href: "/auth/login", class: "hover:text-green-400", "Add account" } }
+        li {
+            form { action: "/logout", method: "post",
+                button { r#type: "submit", class: "hover:text-red-400",
+                    if status.accounts.len() > 1 { "Log out all" } else { "Logout" }
+                }
+            }
+        }
+    }
+}
+
 #[component]
 pub fn Footer() -> Element {
     rsx! {
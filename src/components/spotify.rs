@@ -29,12 +29,19 @@ pub fn ProfileView(profile: SpotifyUserProfile) -> Element {
 }
 
 
+/// Clicking a playlist adds it to `selected_playlists` or takes it out again, in the order picked.
+/// `current_user_id` tells the caller's own playlists from followed ones, pass `None` while unknown
 #[component]
-pub fn PlaylistsView(playlists: Vec<SpotifyPlaylistItem>, selected_playlist: Signal<Option<SpotifyPlaylistItem>>) -> Element {
-    let currently_selected_id_opt: Option<String> = selected_playlist
+pub fn PlaylistsView(
+    playlists: Vec<SpotifyPlaylistItem>,
+    selected_playlists: Signal<Vec<SpotifyPlaylistItem>>,
+    current_user_id: Option<String>,
+) -> Element {
+    let selected_ids: Vec<String> = selected_playlists
         .read()
-        .as_ref()
-        .map(|p| p.id.clone());
+        .iter()
+        .map(|p| p.id.clone())
+        .collect();
 
     rsx! {
         div {
@@ -45,10 +52,7 @@ pub fn PlaylistsView(playlists: Vec<SpotifyPlaylistItem>, selected_playlist: Sig
                         li{class: "text-gray-400 p-3 text-center", "No playlists to display"}
                     }else{
                         {playlists.iter().map(|playlist_item| {
-                            let is_selected = match &currently_selected_id_opt {
-                                Some(selected_id_str) => &playlist_item.id == selected_id_str,
-                                None => false,
-                            };
+                            let is_selected = selected_ids.contains(&playlist_item.id);
 
                             let item_classes = if is_selected {
                                 "bg-green-700 p-3 rounded-md shadow flex items-center justify-between transition-colors cursor-pointer"
@@ -57,7 +61,7 @@ pub fn PlaylistsView(playlists: Vec<SpotifyPlaylistItem>, selected_playlist: Sig
                             };
 
                             let item_for_click_closure = playlist_item.clone();
-                            let mut signal_for_click_closure = selected_playlist;
+                            let mut signal_for_click_closure = selected_playlists;
 
                             rsx! {
                                 li {
@@ -65,11 +69,17 @@ pub fn PlaylistsView(playlists: Vec<SpotifyPlaylistItem>, selected_playlist: Sig
                                     class: "{item_classes}",
                                     onclick: move |_| {
                                         //log::info!("clicked playlistL{}",item_for_click_closure.id);
-                                        signal_for_click_closure.set(Some(item_for_click_closure.clone()));
+                                        let mut selected = signal_for_click_closure.write();
+                                        match selected.iter().position(|p| p.id == item_for_click_closure.id) {
+                                            Some(index) => { selected.remove(index); }
+                                            None => selected.push(item_for_click_closure.clone()),
+                                        }
                                     },
                                     div { // For image and name/description
                                         class: "flex items-center space-x-3",
-                                        if let Some(images) = &playlist_item.images {
+                                        if playlist_item.is_liked_songs() {
+                                            div { class: "w-12 h-12 bg-gradient-to-br from-indigo-600 to-gray-300 rounded flex items-center justify-center text-xl text-white", "♥" }
+                                        } else if let Some(images) = &playlist_item.images {
                                             if let Some(image) = images.first() {
                                                 img {
                                                     src: "{image.url}",
@@ -91,6 +101,7 @@ pub fn PlaylistsView(playlists: Vec<SpotifyPlaylistItem>, selected_playlist: Sig
                                             }
                                         }
                                     }
+                                    PlaylistBadges { playlist: playlist_item.clone(), current_user_id: current_user_id.clone() }
                                 }
                             }
                         })}
@@ -100,3 +111,25 @@ pub fn PlaylistsView(playlists: Vec<SpotifyPlaylistItem>, selected_playlist: Sig
         }
     }
 
+
+#[component]
+fn PlaylistBadges(playlist: SpotifyPlaylistItem, current_user_id: Option<String>) -> Element {
+    let owner_label = match &current_user_id {
+        _ if playlist.is_liked_songs() => Some("Your library".to_string()),
+        Some(user_id) if playlist.is_owned_by(user_id) => Some("Owned by you".to_string()),
+        Some(_) => Some(format!("By {}", playlist.owner.display_name.as_deref().unwrap_or(&playlist.owner.id))),
+        None => None,
+    };
+
+    rsx! {
+        div { class: "flex flex-col items-end space-y-1 text-xs text-gray-300 shrink-0",
+            span { "{playlist.tracks.total} tracks" }
+            if let Some(owner_label) = owner_label {
+                span { class: "text-gray-400", "{owner_label}" }
+            }
+            if playlist.collaborative {
+                span { class: "px-2 rounded-full bg-purple-700 text-white", "Collaborative" }
+            }
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use std::{env, fmt, path::Path, time::Duration};
+
+use serde::Deserialize;
+
+use crate::api_models::LIKED_SONGS_SCOPE;
+use crate::auth::session::SessionStore;
+use crate::spotify::retry::RetryPolicy;
+use crate::storage::{self, Storage};
+
+const DEFAULT_CONFIG_FILE: &str = "betterd.toml";
+const DEFAULT_API_BASE_URL: &str = "https://api.spotify.com/v1";
+const DEFAULT_ACCOUNTS_BASE_URL: &str = "https://accounts.spotify.com";
+const DEFAULT_DATABASE_PATH: &str = "betterd_spotify.db";
+const DEFAULT_SCOPES: &[&str] = &[
+    "playlist-read-private",
+    "playlist-read-collaborative",
+    "playlist-modify-public",
+    "playlist-modify-private",
+    "user-read-private",
+    "user-read-email",
+    "ugc-image-upload",
+    LIKED_SONGS_SCOPE,
+];
+
+/// Everything the server needs from the environment, loaded and checked once in `start_server`.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub spotify: SpotifyConfig,
+    pub session_secret: Vec<u8>,
+    pub token_encryption_key: [u8; 32],
+    pub database_path: String,
+    pub shuffle: ShuffleDefaults,
+}
+
+#[derive(Clone, Debug)]
+pub struct SpotifyConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+    pub api_base_url: String,
+    pub accounts_base_url: String,
+    pub scopes: Vec<String>,
+    pub retry: RetryPolicy,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShuffleDefaults {
+    /// Appended to the source playlist's name
+    pub playlist_suffix: String,
+    pub public: bool,
+    /// Tracks per add-tracks request, Spotify allows at most 100
+    pub add_chunk_size: usize,
+    /// Pause between add-tracks requests
+    pub chunk_delay_ms: u64,
+}
+
+impl Default for ShuffleDefaults {
+    fn default() -> Self {
+        Self {
+            playlist_suffix: " - TRUE SHUFFLED".to_string(),
+            public: false,
+            add_chunk_size: 100,
+            chunk_delay_ms: 250,
+        }
+    }
+}
+
+/// Shape of the optional TOML file, every key can also come from the environment
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    spotify: FileSpotify,
+    server: FileServer,
+    shuffle: FileShuffle,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct FileSpotify {
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    redirect_uri: Option<String>,
+    api_base_url: Option<String>,
+    accounts_base_url: Option<String>,
+    scopes: Option<Vec<String>>,
+    max_attempts: Option<u32>,
+    request_deadline_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct FileServer {
+    session_secret: Option<String>,
+    token_encryption_key: Option<String>,
+    database_path: Option<String>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct FileShuffle {
+    playlist_suffix: Option<String>,
+    public: Option<bool>,
+    add_chunk_size: Option<usize>,
+    chunk_delay_ms: Option<u64>,
+}
+
+/// Every problem found while loading, so they can all be fixed in one go
+#[derive(Debug)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid configuration:")?;
+        for problem in &self.problems {
+            writeln!(f, "  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl SpotifyConfig {
+    /// Web API endpoint, e.g. `api_url("me/playlists")`
+    pub fn api_url(&self, path: &str) -> reqwest::Url {
+        join_url(&self.api_base_url, path)
+    }
+
+    /// Accounts service endpoint, e.g. `accounts_url("api/token")`
+    pub fn accounts_url(&self, path: &str) -> reqwest::Url {
+        join_url(&self.accounts_base_url, path)
+    }
+}
+
+impl Config {
+    /// Reads `BETTERD_CONFIG` (or `betterd.toml` if it exists) and layers the environment on top
+    pub fn load() -> Result<Self, ConfigError> {
+        let file = match env::var("BETTERD_CONFIG") {
+            Ok(path) => read_file(&path)?,
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => read_file(DEFAULT_CONFIG_FILE)?,
+            Err(_) => FileConfig::default(),
+        };
+        Self::from_sources(file, |key| env::var(key).ok().filter(|value| !value.is_empty()))
+    }
+
+    /// Environment values win over the file
+    pub fn from_sources(file: FileConfig, env: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let mut problems = Vec::new();
+
+        let mut required = |key: &str, from_file: Option<String>| {
+            env(key).or(from_file).unwrap_or_else(|| {
+                problems.push(format!("{} is not set", key));
+                String::new()
+            })
+        };
+        let client_id = required("SPOTIFY_CLIENT_ID", file.spotify.client_id);
+        let client_secret = required("SPOTIFY_CLIENT_SECRET", file.spotify.client_secret);
+
+        // older setups (and the README) used REDIRECT_URI
+        let redirect_uri = match env("SPOTIFY_REDIRECT_URI").or_else(|| env("REDIRECT_URI")).or(file.spotify.redirect_uri) {
+            Some(uri) => uri,
+            None => {
+                problems.push("SPOTIFY_REDIRECT_URI is not set".to_string());
+                String::new()
+            }
+        };
+        if !redirect_uri.is_empty() && reqwest::Url::parse(&redirect_uri).is_err() {
+            problems.push(format!("SPOTIFY_REDIRECT_URI is not a valid URL: {}", redirect_uri));
+        }
+
+        let api_base_url = base_url(
+            "SPOTIFY_API_BASE_URL",
+            env("SPOTIFY_API_BASE_URL").or(file.spotify.api_base_url),
+            DEFAULT_API_BASE_URL,
+            &mut problems,
+        );
+        let accounts_base_url = base_url(
+            "SPOTIFY_ACCOUNTS_BASE_URL",
+            env("SPOTIFY_ACCOUNTS_BASE_URL").or(file.spotify.accounts_base_url),
+            DEFAULT_ACCOUNTS_BASE_URL,
+            &mut problems,
+        );
+
+        let scopes: Vec<String> = match env("SPOTIFY_SCOPES") {
+            Some(scopes) => scopes.split_whitespace().map(str::to_string).collect(),
+            None => file
+                .spotify
+                .scopes
+                .unwrap_or_else(|| DEFAULT_SCOPES.iter().map(|s| s.to_string()).collect()),
+        };
+        if scopes.is_empty() {
+            problems.push("SPOTIFY_SCOPES must list at least one scope".to_string());
+        }
+
+        let default_retry = RetryPolicy::default();
+        let retry = RetryPolicy {
+            max_attempts: file.spotify.max_attempts.unwrap_or(default_retry.max_attempts),
+            deadline: file
+                .spotify
+                .request_deadline_secs
+                .map(Duration::from_secs)
+                .unwrap_or(default_retry.deadline),
+            ..default_retry
+        };
+        if retry.max_attempts == 0 {
+            problems.push("spotify.max_attempts must be at least 1".to_string());
+        }
+
+        let database_path = env("DATABASE_PATH")
+            .or(file.server.database_path)
+            .unwrap_or_else(|| DEFAULT_DATABASE_PATH.to_string());
+        let persistent = database_path != storage::IN_MEMORY;
+
+        // with an in-memory database nothing survives a restart anyway, so random keys are fine
+        let session_secret = match env("SESSION_SECRET").or(file.server.session_secret) {
+            Some(secret) if secret.len() < 32 => {
+                problems.push("SESSION_SECRET must be at least 32 characters".to_string());
+                Vec::new()
+            }
+            Some(secret) => secret.into_bytes(),
+            None if persistent => {
+                problems.push("SESSION_SECRET is not set, it is required when DATABASE_PATH is a file".to_string());
+                Vec::new()
+            }
+            None => SessionStore::random_secret(),
+        };
+
+        let token_encryption_key = match env("TOKEN_ENCRYPTION_KEY").or(file.server.token_encryption_key) {
+            Some(key) => Storage::parse_key(&key).unwrap_or_else(|e| {
+                problems.push(format!("TOKEN_ENCRYPTION_KEY: {}", e));
+                [0; 32]
+            }),
+            None if persistent => {
+                problems.push("TOKEN_ENCRYPTION_KEY is not set, it is required when DATABASE_PATH is a file".to_string());
+                [0; 32]
+            }
+            None => Storage::random_key(),
+        };
+
+        let defaults = ShuffleDefaults::default();
+        let shuffle = ShuffleDefaults {
+            playlist_suffix: file.shuffle.playlist_suffix.unwrap_or(defaults.playlist_suffix),
+            public: file.shuffle.public.unwrap_or(defaults.public),
+            add_chunk_size: file.shuffle.add_chunk_size.unwrap_or(defaults.add_chunk_size),
+            chunk_delay_ms: file.shuffle.chunk_delay_ms.unwrap_or(defaults.chunk_delay_ms),
+        };
+        if !(1..=100).contains(&shuffle.add_chunk_size) {
+            problems.push(format!("shuffle.add_chunk_size must be between 1 and 100, got {}", shuffle.add_chunk_size));
+        }
+
+        if !problems.is_empty() {
+            return Err(ConfigError { problems });
+        }
+
+        Ok(Self {
+            spotify: SpotifyConfig {
+                client_id,
+                client_secret,
+                redirect_uri,
+                api_base_url,
+                accounts_base_url,
+                scopes,
+                retry,
+            },
+            session_secret,
+            token_encryption_key,
+            database_path,
+            shuffle,
+        })
+    }
+
+    /// Cookies only get the Secure flag when we are actually served over https
+    pub fn secure_cookies(&self) -> bool {
+        self.spotify.redirect_uri.starts_with("https://")
+    }
+}
+
+// base urls are validated at load time and stored without a trailing slash
+fn join_url(base: &str, path: &str) -> reqwest::Url {
+    reqwest::Url::parse(&format!("{}/{}", base, path.trim_start_matches('/')))
+        .expect("base url was validated when the config was loaded")
+}
+
+fn read_file(path: &str) -> Result<FileConfig, ConfigError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| ConfigError {
+        problems: vec![format!("failed to read config file {}: {}", path, e)],
+    })?;
+    toml::from_str(&contents).map_err(|e| ConfigError {
+        problems: vec![format!("failed to parse config file {}: {}", path, e)],
+    })
+}
+
+fn base_url(key: &str, value: Option<String>, default: &str, problems: &mut Vec<String>) -> String {
+    let url = value.unwrap_or_else(|| default.to_string());
+    match reqwest::Url::parse(&url) {
+        Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => url.trim_end_matches('/').to_string(),
+        _ => {
+            problems.push(format!("{} is not a valid http(s) URL: {}", key, url));
+            url
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Loads `file` with a working environment, changed by `env`; an empty value unsets a key
+    fn load(file: &str, env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let file: FileConfig = toml::from_str(file).unwrap();
+        let mut vars: HashMap<String, String> = [
+            ("SPOTIFY_CLIENT_ID", "client"),
+            ("SPOTIFY_CLIENT_SECRET", "secret"),
+            ("SPOTIFY_REDIRECT_URI", "http://127.0.0.1:8080/callback"),
+            ("DATABASE_PATH", storage::IN_MEMORY),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        for (key, value) in env {
+            if value.is_empty() {
+                vars.remove(*key);
+            } else {
+                vars.insert(key.to_string(), value.to_string());
+            }
+        }
+        Config::from_sources(file, |key| vars.get(key).cloned())
+    }
+
+    fn problems(result: Result<Config, ConfigError>) -> Vec<String> {
+        result.expect_err("the config should be rejected").problems
+    }
+
+    #[test]
+    fn a_file_backed_database_needs_its_secrets() {
+        let problems = problems(load("", &[("DATABASE_PATH", "betterd.db")]));
+
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("SESSION_SECRET is not set"));
+        assert!(problems[1].starts_with("TOKEN_ENCRYPTION_KEY is not set"));
+
+        let config = load("", &[]).unwrap();
+        assert_eq!(config.database_path, storage::IN_MEMORY, "an in-memory database makes up its own");
+    }
+
+    #[test]
+    fn redirect_uri_is_still_read_under_its_old_name() {
+        let config = load("", &[("SPOTIFY_REDIRECT_URI", ""), ("REDIRECT_URI", "https://betterd.example/callback")]).unwrap();
+        assert_eq!(config.spotify.redirect_uri, "https://betterd.example/callback");
+        assert!(config.secure_cookies());
+
+        let problems = problems(load("", &[("SPOTIFY_REDIRECT_URI", "")]));
+        assert_eq!(problems, ["SPOTIFY_REDIRECT_URI is not set"]);
+    }
+
+    #[test]
+    fn base_urls_must_be_http() {
+        let problems = problems(load(
+            "[spotify]\napi_base_url = \"ftp://api.example\"",
+            &[("SPOTIFY_ACCOUNTS_BASE_URL", "not a url")],
+        ));
+
+        assert_eq!(problems, [
+            "SPOTIFY_API_BASE_URL is not a valid http(s) URL: ftp://api.example",
+            "SPOTIFY_ACCOUNTS_BASE_URL is not a valid http(s) URL: not a url",
+        ]);
+
+        let config = load("", &[("SPOTIFY_API_BASE_URL", "http://127.0.0.1:9000/v1/")]).unwrap();
+        assert_eq!(config.spotify.api_url("me").as_str(), "http://127.0.0.1:9000/v1/me");
+    }
+
+    #[test]
+    fn add_chunk_size_must_fit_spotify_s_limit() {
+        for size in [0, 101] {
+            let problems = problems(load(&format!("[shuffle]\nadd_chunk_size = {}", size), &[]));
+            assert_eq!(problems, [format!("shuffle.add_chunk_size must be between 1 and 100, got {}", size)]);
+        }
+        assert_eq!(load("[shuffle]\nadd_chunk_size = 100", &[]).unwrap().shuffle.add_chunk_size, 100);
+    }
+
+    #[test]
+    fn the_environment_wins_over_the_file() {
+        let file = "[spotify]\nclient_id = \"from-file\"\nscopes = [\"playlist-read-private\"]\n\n[shuffle]\npublic = true";
+
+        let config = load(file, &[("SPOTIFY_CLIENT_ID", "from-env")]).unwrap();
+        assert_eq!(config.spotify.client_id, "from-env");
+        assert_eq!(config.spotify.scopes, ["playlist-read-private"], "the file fills what the environment doesn't set");
+        assert!(config.shuffle.public);
+
+        let config = load(file, &[("SPOTIFY_CLIENT_ID", "")]).unwrap();
+        assert_eq!(config.spotify.client_id, "from-file");
+    }
+
+    #[test]
+    fn the_example_config_lists_the_default_scopes() {
+        let example = include_str!("../betterd.example.toml");
+        let line = example
+            .lines()
+            .find_map(|line| line.strip_prefix("# scopes = "))
+            .expect("the example documents the scopes");
+
+        let config = load(&format!("[spotify]\nscopes = {}", line), &[]).unwrap();
+        assert_eq!(config.spotify.scopes, DEFAULT_SCOPES);
+    }
+}
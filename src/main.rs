@@ -8,7 +8,18 @@ mod routes;
 mod server;
 #[cfg(feature = "server")]
 mod auth;
+#[cfg(feature = "server")]
+mod storage;
+#[cfg(feature = "server")]
+mod config;
+#[cfg(feature = "server")]
+mod spotify;
+#[cfg(feature = "server")]
+mod shuffle;
+#[cfg(all(test, feature = "server"))]
+mod test_support;
 pub mod api;
+pub mod app_error;
 pub mod api_models;
 
 use crate::components::layout::*;
@@ -56,12 +67,14 @@ enum Route {
     #[layout(NavBar)]
     #[route("/")]
     Home {},
-    #[route("/login")]
-    LoginPage {},
+    #[route("/login?:error")]
+    LoginPage { error: String },
     #[route("/shuffle")]
     ShufflePage{},
     #[route("/shuffle/:playlist_id/:playlist_name")]
     ShuffleActionPage{playlist_id:String, playlist_name: String},
+    #[route("/merge?:ids")]
+    MergeActionPage{ids: String},
     #[route("/callback")]
     CallBack{},
 }
@@ -1,15 +1,45 @@
 use dioxus::prelude::*;
-use crate::api::{get_spotify_user_playlists_all, get_spotify_user_profile};
-use crate::api_models::{SpotifyPlaylistItem, SpotifyUserProfile};
+use crate::api::{get_auth_status, get_spotify_user_playlists_all, get_spotify_user_profile};
+use crate::api_models::{SpotifyPlaylistItem, SpotifyUserProfile, LIKED_SONGS_SCOPE};
+use crate::app_error::AppError;
+use crate::components::error::ErrorView;
 use crate::components::spotify::{PlaylistsView, ProfileView};
 use crate::Route;
 
+/// Above this many tracks the shuffle page warns that the shuffle will take a while
+const LARGE_PLAYLIST_TRACKS: u32 = 2000;
+
+fn large_playlist_warning(playlist: &SpotifyPlaylistItem) -> Option<String> {
+    let total = playlist.tracks.total;
+    // a page read and an add per 100 tracks
+    (total > LARGE_PLAYLIST_TRACKS).then(|| format!(
+        "\"{}\" has {} tracks. Shuffling it takes about {} requests to Spotify and can run for a minute or more.",
+        playlist.name,
+        total,
+        total.div_ceil(100) * 2,
+    ))
+}
+
 #[component]
 pub fn ShufflePage() -> Element{
-    let playlists_resource : Resource<Result<Vec<SpotifyPlaylistItem>,ServerFnError>> = use_server_future(|| async{
+    let mut playlists_resource : Resource<Result<Vec<SpotifyPlaylistItem>,ServerFnError<AppError>>> = use_server_future(|| async{
     get_spotify_user_playlists_all().await})?;
+    let auth_status = use_server_future(|| async { get_auth_status().await })?;
+    let current_user_id = auth_status
+        .read()
+        .as_ref()
+        .and_then(|status| status.as_ref().ok())
+        .and_then(|status| status.accounts.iter().find(|account| account.active))
+        .map(|account| account.spotify_id.clone());
+    // logins from before Liked Songs could be shuffled didn't grant reading the library
+    let missing_library_scope = auth_status
+        .read()
+        .as_ref()
+        .and_then(|status| status.as_ref().ok())
+        .is_some_and(|status| status.logged_in && !status.scopes.iter().any(|scope| scope == LIKED_SONGS_SCOPE));
     let mut search_term = use_signal(String::new);
-    let selected_playlist : Signal<Option<SpotifyPlaylistItem>> = use_signal(|| None);
+    // several picked playlists are merged
+    let selected_playlists : Signal<Vec<SpotifyPlaylistItem>> = use_signal(Vec::new);
 
     let navigator = use_navigator();
 
@@ -20,7 +50,7 @@ pub fn ShufflePage() -> Element{
                     h1 { class: "text-3xl font-bold text-green-400 mb-2",
                         "Playlist Shuffler Studio"
                     }
-                    p { class: "text-lg text-gray-300", "Select or search for a playlist to begin..." }
+                    p { class: "text-lg text-gray-300", "Select or search for a playlist to begin, pick several to merge them..." }
                 }
             //Search
             div {
@@ -38,6 +68,12 @@ pub fn ShufflePage() -> Element{
                 id: "shuffle-playlist-selection-list",
                 class: "bg-gray-800 p-4 md:p-6 rounded-lg shadow-lg",
                 h2 { class: "text-2xl font-semibold text-green-300 mb-4 border-b border-gray-700 pb-2", "Your Playlists" }
+                if missing_library_scope {
+                    div { class: "mb-4 bg-gray-900 rounded-lg",
+                        p { class: "text-gray-300 pt-4 text-center", "Reconnect to shuffle your Liked Songs too." }
+                        ErrorView { error: AppError::MissingScope { message: format!("the session lacks {}", LIKED_SONGS_SCOPE) } }
+                    }
+                }
                 {
                     match playlists_resource.read().as_ref() {
                         Some(Ok(all_playlists_vec)) => {
@@ -61,12 +97,19 @@ pub fn ShufflePage() -> Element{
                                 // Pass down the selected_playlist signal and filtered list
                                 rsx!{PlaylistsView {
                                     playlists: filtered_playlists,
-                                    selected_playlist: selected_playlist // Pass the signal
+                                    selected_playlists: selected_playlists, // Pass the signal
+                                    current_user_id: current_user_id.clone(),
                                 }}
                             }
                         }
                         Some(Err(e)) => {
-                            rsx! { p { class: "text-red-400 text-center py-4", "Error loading playlists: {e}" } }
+                            rsx! {
+                                p { class: "text-red-400 text-center", "Error loading playlists" }
+                                ErrorView {
+                                    error: AppError::from_server_fn_error(e),
+                                    on_retry: move |_| playlists_resource.restart(),
+                                }
+                            }
                         }
                         None => {rsx! {p { "Loading playlists..."}}}
                     }
@@ -74,21 +117,31 @@ pub fn ShufflePage() -> Element{
             }
             div {
                 class: "mt-6 text-center",
+                for warning in selected_playlists.read().iter().filter_map(large_playlist_warning) {
+                    p { class: "text-yellow-400 mb-3", "{warning}" }
+                }
                 button {
-                    disabled: selected_playlist.read().is_none(), // Enable only if a playlist is selected
+                    disabled: selected_playlists.read().is_empty(), // Enable only if a playlist is selected
                     class: "px-6 py-3 text-lg font-semibold text-white bg-blue-600 rounded-lg shadow hover:bg-blue-700 disabled:opacity-50 disabled:bg-gray-500 disabled:cursor-not-allowed transition-opacity",
                     onclick: move |_| {
-                        if let Some(playlist) = selected_playlist.read().as_ref() {
-                            navigator.push(Route::ShuffleActionPage {
-                                playlist_id: playlist.id.clone(),
-                                playlist_name: playlist.name.clone(),
-                            });
+                        match selected_playlists.read().as_slice() {
+                            [] => {}
+                            [playlist] => {
+                                navigator.push(Route::ShuffleActionPage {
+                                    playlist_id: playlist.id.clone(),
+                                    playlist_name: playlist.name.clone(),
+                                });
+                            }
+                            several => {
+                                let ids: Vec<&str> = several.iter().map(|p| p.id.as_str()).collect();
+                                navigator.push(Route::MergeActionPage { ids: ids.join(",") });
+                            }
                         }
                     },
-                    if selected_playlist.read().is_some() {
-                        "Prepare Shuffle for \"{selected_playlist.read().as_ref().unwrap().name}\""
-                    } else {
-                        "Select a Playlist to Prepare Shuffle"
+                    match selected_playlists.read().as_slice() {
+                        [] => rsx! { "Select a Playlist to Prepare Shuffle" },
+                        [playlist] => rsx! { "Prepare Shuffle for \"{playlist.name}\"" },
+                        several => rsx! { "Prepare Merge of {several.len()} Playlists" },
                     }
                 }
             }
@@ -100,7 +153,7 @@ pub fn ShufflePage() -> Element{
 
 #[component]
 pub fn Home() -> Element {
-    let profile_resource: Resource<Result<SpotifyUserProfile, ServerFnError>> = use_server_future( || async {
+    let mut profile_resource: Resource<Result<SpotifyUserProfile, ServerFnError<AppError>>> = use_server_future( || async {
         get_spotify_user_profile().await})?;
 
 
@@ -127,7 +180,13 @@ pub fn Home() -> Element {
                 {
                     match profile_resource.read().as_ref() {
                         Some(Ok(profile)) => rsx! { ProfileView { profile: profile.clone() } },
-                        Some(Err(e)) => rsx! { p { class: "text-red-400", "Error loading profile: {e}" } },
+                        Some(Err(e)) => rsx! {
+                            p { class: "text-red-400", "Error loading profile" }
+                            ErrorView {
+                                error: AppError::from_server_fn_error(e),
+                                on_retry: move |_| profile_resource.restart(),
+                            }
+                        },
                         None => rsx! { p { class: "text-yellow-400", "Loading profile..." } }
                     }
                 }
@@ -136,8 +195,20 @@ pub fn Home() -> Element {
     }
 }
 
+// Error codes the Axum auth handlers put in /login?error=...
+fn login_error_message(error: &str) -> Option<&'static str> {
+    match error {
+        "" => None,
+        "state_expired" => Some("That login took too long and expired. Please try again."),
+        "state_mismatch" | "missing_state" => Some("We couldn't match that login to one we started. Please try again."),
+        "missing_code" => Some("Spotify didn't authorize the login."),
+        "network_error" => Some("We couldn't reach Spotify. Please try again in a moment."),
+        _ => Some("Something went wrong while logging you in. Please try again."),
+    }
+}
+
 #[component]
-pub fn LoginPage() -> Element {
+pub fn LoginPage(error: String) -> Element {
     rsx! {
        div {
             class: "flex-grow flex flex-col items-center justify-center p-4", // Centers content vertically and horizontally
@@ -151,13 +222,16 @@ pub fn LoginPage() -> Element {
                     class: "text-3xl md:text-4xl font-bold text-green-400 mb-4",
                     "Access Your Music"
                 }
+                if let Some(message) = login_error_message(&error) {
+                    p { class: "text-red-400 mb-4", "{message}" }
+                }
                 p {
                     class: "text-gray-300 mb-8 text-lg",
                     "Please log in with your Spotify account to continue to betterdSpotify and experience true playlist shuffling!"
                 }
 
                 a {
-                    href: "/login", // This path is handled by your Axum server
+                    href: "/auth/login", // This path is handled by your Axum server
                     class: "inline-block w-full sm:w-auto px-8 py-3 text-lg font-semibold text-white bg-green-500 rounded-lg shadow-md hover:bg-green-600 focus:outline-none focus:ring-2 focus:ring-green-400 focus:ring-opacity-75 transition-colors duration-150",
                     "Login with Spotify"
                 }
@@ -1,8 +1,11 @@
 use dioxus::prelude::*;
-use crate::api::{get_spotify_playlist_tracks_all, get_spotify_user_playlists_all, get_spotify_user_profile, shuffle_and_save_new_playlist};
-use crate::api_models::{NewPlaylistDetails, SpotifyPlaylistItem, SpotifyTrackItem, SpotifyUserProfile};
-use crate::components::spotify::{PlaylistsView, ProfileView};
-use crate::Route;
+use crate::api::{
+    get_shuffle_strategies, get_spotify_playlist_tracks_all, get_spotify_user_playlists_all, merge_and_shuffle_playlists,
+    shuffle_and_save_new_playlist, undo_in_place_shuffle,
+};
+use crate::api_models::{AddTracksReport, MergeContribution, MergeSource, NewPlaylistDetails, ShuffleDestination, SpotifyPlaylistItem, ShuffleStrategyInfo, ShuffleSummary, SpotifyTrackItem};
+use crate::app_error::AppError;
+use crate::components::error::ErrorView;
 
 // --- Shuffle Action Stages ---
 #[derive(PartialEq, Clone, Debug)]
@@ -10,8 +13,8 @@ pub enum ShuffleStage { // Make pub if used across modules, fine for now if only
     Idle,
     FetchingTracks,
     ShufflingAndCreatingPlaylist { num_tracks_to_shuffle: usize },
-    Completed(NewPlaylistDetails),
-    Error(String),
+    Completed(Box<NewPlaylistDetails>),
+    Error(AppError),
 }
 
 // --- Stage-Specific View Components ---
@@ -27,14 +30,20 @@ fn FetchingTracksView(playlist_name: String) -> Element {
 }
 
 #[component]
-fn ShufflingAndCreatingView(playlist_name: String, num_tracks: usize) -> Element {
+fn ShufflingAndCreatingView(playlist_name: String, num_tracks: usize, destination: ShuffleDestination) -> Element {
+    let doing = match destination {
+        ShuffleDestination::NewPlaylist => "Shuffling and creating your new playlist...",
+        ShuffleDestination::ShuffledCopy => "Shuffling into your shuffled copy...",
+        ShuffleDestination::InPlace => "Shuffling and reordering the playlist itself...",
+    };
+
     rsx! {
         div { class: "text-center p-4",
             div { class: "animate-spin rounded-full h-12 w-12 border-t-4 border-b-4 border-purple-500 mx-auto mb-4" }
             p { class: "text-xl text-yellow-400",
                 "Processing {num_tracks} tracks for \"{playlist_name}\"."
             }
-            p { class: "text-sm text-gray-400 mt-2", "Shuffling and creating your new playlist..."}
+            p { class: "text-sm text-gray-400 mt-2", "{doing}" }
         }
     }
 }
@@ -44,12 +53,26 @@ fn ShuffleCompleteView(details: NewPlaylistDetails) -> Element {
     rsx! {
         div { class: "text-center p-4",
             p { class: "text-2xl text-green-500 mb-3", "＼(＾▽＾)／ True Shuffle Complete! ＼(＾▽＾)／" }
-            p { class: "text-gray-200 mb-1", "New playlist created:" }
+            p { class: "text-gray-200 mb-1",
+                match details.saved_to {
+                    ShuffleDestination::NewPlaylist => "New playlist created:",
+                    ShuffleDestination::ShuffledCopy => "Shuffled copy updated:",
+                    ShuffleDestination::InPlace => "Playlist reordered:",
+                }
+            }
             p { class: "text-xl font-semibold text-gray-100 mb-4", "\"{details.name}\"" }
+            if !details.merged_from.is_empty() {
+                MergeSummaryView { merged_from: details.merged_from.clone() }
+            }
+            ShuffleReportView { report: details.report.clone() }
+            SpreadSummaryView { summary: details.summary.clone() }
             a {
                 href: "{details.external_url}", target: "_blank", rel: "noopener noreferrer",
                 class: "inline-block px-6 py-3 text-white bg-spotify-green rounded-lg hover:bg-opacity-80 shadow-md", // Define bg-spotify-green or use existing
-                "Open New Playlist on Spotify"
+                "Open Playlist on Spotify"
+            }
+            if details.saved_to == ShuffleDestination::InPlace {
+                UndoButton { playlist_id: details.id.clone() }
             }
             // Optional: Button to go back to shuffle selection or home
             // Link { to: Route::ShuffleSelectPage {}, class: "mt-6 inline-block text-sm text-blue-400 hover:underline", "Shuffle Another?" }
@@ -58,16 +81,196 @@ fn ShuffleCompleteView(details: NewPlaylistDetails) -> Element {
 }
 
 #[component]
-fn ShuffleErrorView(error_message: String, on_retry: EventHandler<()>) -> Element {
+fn MergeSummaryView(merged_from: Vec<MergeContribution>) -> Element {
+    rsx! {
+        ul { class: "text-sm text-gray-400 mb-4",
+            for contribution in merged_from {
+                li {
+                    "\"{contribution.name}\": {contribution.taken} tracks"
+                    if contribution.duplicates > 0 { ", {contribution.duplicates} duplicates left out" }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn UndoButton(playlist_id: String) -> Element {
+    // None until clicked, then whether the undo went through
+    let mut undone: Signal<Option<Result<(), AppError>>> = use_signal(|| None);
+
+    rsx! {
+        div { class: "mt-4",
+            match &*undone.read() {
+                None => rsx! {
+                    button {
+                        class: "text-sm text-blue-400 hover:underline",
+                        onclick: move |_| {
+                            let playlist_id = playlist_id.clone();
+                            spawn(async move {
+                                let result = undo_in_place_shuffle(playlist_id).await;
+                                undone.set(Some(result.map_err(|e| AppError::from_server_fn_error(&e))));
+                            });
+                        },
+                        "Undo: put the old order back"
+                    }
+                },
+                Some(Ok(())) => rsx! { p { class: "text-sm text-gray-400", "The old order is back." } },
+                Some(Err(error)) => rsx! { ErrorView { error: error.clone() } },
+            }
+        }
+    }
+}
+
+#[component]
+fn SpreadSummaryView(summary: ShuffleSummary) -> Element {
+    let gap = |gap: Option<u32>| match gap {
+        Some(1) => "back to back at times".to_string(),
+        Some(gap) => format!("at least {} tracks apart", gap),
+        None => "never repeated".to_string(),
+    };
+
+    rsx! {
+        p { class: "text-sm text-gray-400 mb-1",
+            "Same artist {gap(summary.min_artist_gap)}, same album {gap(summary.min_album_gap)}."
+        }
+        p { class: "text-sm text-gray-400 mb-4",
+            "Seed {summary.seed}: shuffle the unchanged playlist with it again for the same order."
+        }
+    }
+}
+
+#[component]
+fn ShuffleReportView(report: AddTracksReport) -> Element {
+    if report.is_complete() {
+        return rsx! { p { class: "text-gray-400 mb-4", "All {report.added.len()} tracks were added." } };
+    }
+    let spotify_total = match report.verified_total {
+        Some(total) => total.to_string(),
+        None => "an unknown number of".to_string(),
+    };
+
+    rsx! {
+        div { class: "text-left bg-gray-900 rounded-lg p-4 mb-4",
+            p { class: "text-yellow-400 mb-2",
+                "Added {report.added.len()} tracks, Spotify shows {spotify_total} in the new playlist."
+            }
+            if !report.failed.is_empty() {
+                details { class: "mb-2",
+                    summary { class: "text-red-400 cursor-pointer", "{report.failed.len()} tracks Spotify wouldn't add" }
+                    ul { class: "text-xs text-gray-400 mt-1",
+                        for uri in report.failed.iter() { li { "{uri}" } }
+                    }
+                }
+            }
+            if !report.skipped.is_empty() {
+                details {
+                    summary { class: "text-gray-300 cursor-pointer", "{report.skipped.len()} local files skipped" }
+                    ul { class: "text-xs text-gray-400 mt-1",
+                        for uri in report.skipped.iter() { li { "{uri}" } }
+                    }
+                }
+            }
+            if let Some(error) = report.stopped_by.clone() {
+                ErrorView { error }
+            }
+        }
+    }
+}
+
+#[component]
+fn StrategyPicker(strategies: Vec<ShuffleStrategyInfo>, selected: Signal<Option<String>>) -> Element {
+    let current = selected.read().clone().or_else(|| strategies.first().map(|s| s.name.clone()));
+
+    rsx! {
+        div { class: "w-full mb-6 space-y-2 text-left",
+            for strategy in strategies {
+                label {
+                    key: "{strategy.name}",
+                    class: "flex items-start space-x-3 p-3 rounded-md bg-gray-700 hover:bg-gray-600 cursor-pointer",
+                    input {
+                        r#type: "radio",
+                        name: "shuffle-strategy",
+                        class: "mt-1",
+                        checked: current.as_deref() == Some(strategy.name.as_str()),
+                        onchange: {
+                            let name = strategy.name.clone();
+                            move |_| selected.set(Some(name.clone()))
+                        },
+                    }
+                    div {
+                        p { class: "font-semibold text-gray-100", "{strategy.label}" }
+                        p { class: "text-xs text-gray-400", "{strategy.description}" }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn SeedInput(seed: Signal<String>) -> Element {
+    rsx! {
+        label { class: "w-full mb-6 text-left block",
+            span { class: "text-sm text-gray-300", "Seed (optional, to repeat an earlier shuffle)" }
+            input {
+                r#type: "text",
+                inputmode: "numeric",
+                class: "mt-1 w-full px-3 py-2 rounded-md bg-gray-700 text-gray-100",
+                placeholder: "Random",
+                value: "{seed}",
+                oninput: move |event| seed.set(event.value()),
+            }
+        }
+    }
+}
+
+#[component]
+fn DestinationPicker(destination: Signal<ShuffleDestination>) -> Element {
+    let options = [
+        (ShuffleDestination::ShuffledCopy, "Update the shuffled copy", "Reuses the copy earlier shuffles made, so it stays pinned where you put it."),
+        (ShuffleDestination::NewPlaylist, "Create a new playlist", "Leaves earlier shuffled copies alone."),
+        (ShuffleDestination::InPlace, "Reorder this playlist", "Only for playlists you own. The old order can be put back afterwards. Long playlists are rewritten, which resets their dates added."),
+    ];
+
+    rsx! {
+        div { class: "w-full mb-6 space-y-2 text-left",
+            for (option, label, description) in options {
+                label {
+                    key: "{label}",
+                    class: "flex items-start space-x-3 p-3 rounded-md bg-gray-700 hover:bg-gray-600 cursor-pointer",
+                    input {
+                        r#type: "radio",
+                        name: "shuffle-destination",
+                        class: "mt-1",
+                        checked: *destination.read() == option,
+                        onchange: move |_| destination.set(option),
+                    }
+                    div {
+                        p { class: "font-semibold text-gray-100", "{label}" }
+                        p { class: "text-xs text-gray-400", "{description}" }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `None` for a blank seed, so the server picks one
+fn parse_seed(input: &str) -> Result<Option<u64>, AppError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(None);
+    }
+    input.parse().map(Some).map_err(|_| AppError::validation(format!("'{}' isn't a seed, seeds are whole numbers.", input)))
+}
+
+#[component]
+fn ShuffleErrorView(error: AppError, on_retry: EventHandler<()>) -> Element {
     rsx! {
         div { class: "text-center p-4",
             p { class: "text-2xl text-red-500 mb-3", "(╯°□°）╯︵ ┻━┻ Shuffle Process Failed!" }
-            p { class: "text-gray-300 mt-2 mb-6", "{error_message}" }
-            button {
-                class: "px-6 py-2 text-white bg-orange-500 rounded-lg hover:bg-orange-600 shadow",
-                onclick: move |_| on_retry.call(()),
-                "Try Again"
-            }
+            ErrorView { error, on_retry }
         }
     }
 }
@@ -80,6 +283,12 @@ pub fn ShuffleActionPage(playlist_id: String, playlist_name: String) -> Element
     // This ensures tracks are fetched only ONCE.
     let mut fetched_tracks_for_shuffle: Signal<Option<Vec<SpotifyTrackItem>>> = use_signal(|| None);
 
+    let strategies = use_resource(get_shuffle_strategies);
+    // None until picked, the server's default (first) strategy is used then
+    let selected_strategy: Signal<Option<String>> = use_signal(|| None);
+    let seed_input = use_signal(String::new);
+    let destination = use_signal(|| ShuffleDestination::ShuffledCopy);
+
     // Clones for async tasks
     let pid_for_tasks = playlist_id.clone();
     let pname_for_tasks = playlist_name.clone();
@@ -96,7 +305,7 @@ pub fn ShuffleActionPage(playlist_id: String, playlist_name: String) -> Element
                     match get_spotify_playlist_tracks_all(pid_clone).await {
                         Ok(tracks) => {
                             if tracks.is_empty() {
-                                stage_signal.set(ShuffleStage::Error("Selected playlist is empty or no tracks were found.".to_string()));
+                                stage_signal.set(ShuffleStage::Error(AppError::validation("Selected playlist is empty or no tracks were found.")));
                             } else {
                                 let num_tracks = tracks.len();
                                 tracks_signal.set(Some(tracks)); // Store fetched tracks
@@ -104,13 +313,13 @@ pub fn ShuffleActionPage(playlist_id: String, playlist_name: String) -> Element
                                 stage_signal.set(ShuffleStage::ShufflingAndCreatingPlaylist { num_tracks_to_shuffle: num_tracks });
                             }
                         }
-                        Err(e) => stage_signal.set(ShuffleStage::Error(format!("Failed to fetch tracks: {}", e))),
+                        Err(e) => stage_signal.set(ShuffleStage::Error(AppError::from_server_fn_error(&e))),
                     }
                 });
             }
             ShuffleStage::ShufflingAndCreatingPlaylist { num_tracks_to_shuffle: _ } => { // num_tracks already set for UI
                 // Check if tracks are actually fetched and stored
-                if let Some(tracks) = fetched_tracks_for_shuffle.read().as_ref() {
+                if fetched_tracks_for_shuffle.read().is_some() {
                     // If `shuffle_and_save_new_playlist` needs the track URIs directly,
                     // extract them here. Otherwise, it might re-fetch based on ID if that's its design.
                     // For now, assuming `shuffle_and_save_new_playlist` takes playlist_id and re-fetches or uses cached if available.
@@ -118,23 +327,35 @@ pub fn ShuffleActionPage(playlist_id: String, playlist_name: String) -> Element
                     // you would extract them from `tracks` here.
 
                     let mut stage_signal = current_stage;
-                    let mut pid_clone = pid_for_tasks.clone();
-                    let mut pname_clone = pname_for_tasks.clone();
+                    let pid_clone = pid_for_tasks.clone();
+                    let pname_clone = pname_for_tasks.clone();
+                    // peek: picking a strategy shouldn't re-run this effect
+                    let strategy = selected_strategy.peek().clone().or_else(|| {
+                        strategies.peek().as_ref().and_then(|list| list.as_ref().ok()?.first().map(|s| s.name.clone()))
+                    }).unwrap_or_else(|| "uniform".to_string());
+                    let seed = match parse_seed(&seed_input.peek()) {
+                        Ok(seed) => seed,
+                        Err(error) => {
+                            stage_signal.set(ShuffleStage::Error(error));
+                            return;
+                        }
+                    };
+                    let destination = *destination.peek();
                     // IMPORTANT: The current `shuffle_and_save_new_playlist` re-fetches tracks.
                     // If you want to avoid re-fetching, modify `shuffle_and_save_new_playlist`
                     // to accept `Vec<SpotifyTrackItem>` or `Vec<String>` (track URIs) as an argument.
                     // For this example, we proceed with its current signature.
 
                     spawn(async move {
-                        match shuffle_and_save_new_playlist(pid_clone, pname_clone).await {
-                            Ok(details) => stage_signal.set(ShuffleStage::Completed(details)),
-                            Err(e) => stage_signal.set(ShuffleStage::Error(format!("Failed to shuffle and save playlist: {}", e))),
+                        match shuffle_and_save_new_playlist(pid_clone, pname_clone, strategy, seed, destination).await {
+                            Ok(details) => stage_signal.set(ShuffleStage::Completed(Box::new(details))),
+                            Err(e) => stage_signal.set(ShuffleStage::Error(AppError::from_server_fn_error(&e))),
                         }
                     });
                 } else {
                     // This case should ideally not be reached if FetchingTracks succeeded.
                     let mut stage_signal = current_stage;
-                    stage_signal.set(ShuffleStage::Error("Track data was lost before shuffling could start. Please retry.".to_string()));
+                    stage_signal.set(ShuffleStage::Error(AppError::internal("Track data was lost before shuffling could start.")));
                 }
             }
             _ => {} // Do nothing for Idle, Completed, Error in this effect
@@ -155,6 +376,11 @@ pub fn ShuffleActionPage(playlist_id: String, playlist_name: String) -> Element
                 class: "bg-gray-800 p-6 rounded-lg shadow-lg max-w-xl mx-auto min-h-[12rem] flex flex-col items-center justify-center",
                 match &*current_stage.read() {
                     ShuffleStage::Idle => rsx! {
+                        if let Some(Ok(list)) = strategies.read().as_ref() {
+                            StrategyPicker { strategies: list.clone(), selected: selected_strategy }
+                        }
+                        SeedInput { seed: seed_input }
+                        DestinationPicker { destination }
                         button {
                             class: "px-8 py-4 text-xl font-semibold text-white bg-purple-600 rounded-lg shadow hover:bg-purple-700 focus:outline-none focus:ring-2 focus:ring-purple-400 focus:ring-opacity-75",
                             onclick: move |_| {
@@ -168,14 +394,14 @@ pub fn ShuffleActionPage(playlist_id: String, playlist_name: String) -> Element
                         FetchingTracksView { playlist_name: playlist_name.clone() }
                     },
                     ShuffleStage::ShufflingAndCreatingPlaylist { num_tracks_to_shuffle } => rsx! {
-                        ShufflingAndCreatingView { playlist_name: playlist_name.clone(), num_tracks: *num_tracks_to_shuffle }
+                        ShufflingAndCreatingView { playlist_name: playlist_name.clone(), num_tracks: *num_tracks_to_shuffle, destination: *destination.read() }
                     },
                     ShuffleStage::Completed(ref details) => rsx! {
-                        ShuffleCompleteView { details: details.clone() }
+                        ShuffleCompleteView { details: (**details).clone() }
                     },
-                    ShuffleStage::Error(ref err_msg) => rsx! {
+                    ShuffleStage::Error(ref error) => rsx! {
                         ShuffleErrorView {
-                            error_message: err_msg.clone(),
+                            error: error.clone(),
                             on_retry: move |_| {
                                 fetched_tracks_for_shuffle.set(None); // Clear previous tracks before retry
                                 current_stage.set(ShuffleStage::Idle);
@@ -187,3 +413,138 @@ pub fn ShuffleActionPage(playlist_id: String, playlist_name: String) -> Element
         }
     }
 }
+
+#[component]
+fn MergeOptions(sources: Vec<SpotifyPlaylistItem>, weights: Signal<Option<Vec<String>>>, dedupe: Signal<bool>) -> Element {
+    let even_share = (100 / sources.len().max(1)).to_string();
+
+    rsx! {
+        div { class: "w-full mb-6 space-y-2 text-left",
+            label { class: "flex items-center space-x-3 cursor-pointer",
+                input {
+                    r#type: "checkbox",
+                    checked: *dedupe.read(),
+                    onchange: move |event| dedupe.set(event.checked()),
+                }
+                span { class: "text-sm text-gray-300", "Only add a song once, even if several playlists have it" }
+            }
+            label { class: "flex items-center space-x-3 cursor-pointer",
+                input {
+                    r#type: "checkbox",
+                    checked: weights.read().is_some(),
+                    onchange: {
+                        let count = sources.len();
+                        move |event: FormEvent| weights.set(event.checked().then(|| vec![even_share.clone(); count]))
+                    },
+                }
+                span { class: "text-sm text-gray-300", "Mix by weight instead of using every song" }
+            }
+            for (index, source) in sources.iter().enumerate() {
+                div { key: "{source.id}", class: "flex items-center justify-between p-3 rounded-md bg-gray-700",
+                    span { class: "text-gray-100", "{source.name} ({source.tracks.total} tracks)" }
+                    if let Some(current) = weights.read().as_ref().map(|weights| weights[index].clone()) {
+                        input {
+                            r#type: "number",
+                            min: "1",
+                            class: "w-20 px-2 py-1 rounded-md bg-gray-800 text-gray-100",
+                            value: "{current}",
+                            oninput: move |event| {
+                                if let Some(weights) = weights.write().as_mut() {
+                                    weights[index] = event.value();
+                                }
+                            },
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Merges the playlists in `ids`, a comma separated list of playlist ids, into one shuffled playlist
+#[component]
+pub fn MergeActionPage(ids: String) -> Element {
+    let playlists = use_server_future(get_spotify_user_playlists_all)?;
+    let strategies = use_resource(get_shuffle_strategies);
+    let selected_strategy: Signal<Option<String>> = use_signal(|| None);
+    let seed_input = use_signal(String::new);
+    // weights as typed, `None` to use every song
+    let weights: Signal<Option<Vec<String>>> = use_signal(|| None);
+    let dedupe = use_signal(|| true);
+    let mut stage = use_signal(|| ShuffleStage::Idle);
+
+    let sources: Vec<SpotifyPlaylistItem> = match playlists.read().as_ref() {
+        Some(Ok(all)) => ids.split(',').filter_map(|id| all.iter().find(|playlist| playlist.id == id).cloned()).collect(),
+        Some(Err(e)) => return rsx! { ErrorView { error: AppError::from_server_fn_error(e) } },
+        None => Vec::new(),
+    };
+    let title = sources.iter().map(|source| format!("\"{}\"", source.name)).collect::<Vec<_>>().join(" + ");
+
+    let start = {
+        let sources = sources.clone();
+        move |_| {
+            let merge_sources: Vec<MergeSource> = sources
+                .iter()
+                .enumerate()
+                .map(|(index, source)| MergeSource {
+                    playlist_id: source.id.clone(),
+                    name: source.name.clone(),
+                    // an unreadable weight is sent as 0 for the server to turn down
+                    weight: weights.read().as_ref().map(|weights| weights[index].trim().parse().unwrap_or(0)),
+                })
+                .collect();
+            let strategy = selected_strategy.read().clone().or_else(|| {
+                strategies.read().as_ref().and_then(|list| list.as_ref().ok()?.first().map(|s| s.name.clone()))
+            }).unwrap_or_else(|| "uniform".to_string());
+            let seed = match parse_seed(&seed_input.read()) {
+                Ok(seed) => seed,
+                Err(error) => return stage.set(ShuffleStage::Error(error)),
+            };
+            let dedupe = *dedupe.read();
+            let num_tracks = sources.iter().map(|source| source.tracks.total as usize).sum();
+
+            stage.set(ShuffleStage::ShufflingAndCreatingPlaylist { num_tracks_to_shuffle: num_tracks });
+            spawn(async move {
+                match merge_and_shuffle_playlists(merge_sources, dedupe, strategy, seed).await {
+                    Ok(details) => stage.set(ShuffleStage::Completed(Box::new(details))),
+                    Err(e) => stage.set(ShuffleStage::Error(AppError::from_server_fn_error(&e))),
+                }
+            });
+        }
+    };
+
+    rsx! {
+        div { class: "p-4 md:p-8 text-center",
+            div { class: "mb-8",
+                h1 { class: "text-3xl md:text-4xl font-bold text-green-400 mb-1", "Merging:" }
+                h2 { class: "text-2xl md:text-3xl font-semibold text-gray-200", "{title}" }
+            }
+            div { class: "bg-gray-800 p-6 rounded-lg shadow-lg max-w-xl mx-auto min-h-[12rem] flex flex-col items-center justify-center",
+                match &*stage.read() {
+                    ShuffleStage::Idle | ShuffleStage::FetchingTracks => rsx! {
+                        MergeOptions { sources: sources.clone(), weights, dedupe }
+                        if let Some(Ok(list)) = strategies.read().as_ref() {
+                            StrategyPicker { strategies: list.clone(), selected: selected_strategy }
+                        }
+                        SeedInput { seed: seed_input }
+                        button {
+                            disabled: sources.len() < 2,
+                            class: "px-8 py-4 text-xl font-semibold text-white bg-purple-600 rounded-lg shadow hover:bg-purple-700 disabled:opacity-50",
+                            onclick: start.clone(),
+                            "Merge and Shuffle!"
+                        }
+                    },
+                    ShuffleStage::ShufflingAndCreatingPlaylist { num_tracks_to_shuffle } => rsx! {
+                        ShufflingAndCreatingView { playlist_name: title.clone(), num_tracks: *num_tracks_to_shuffle, destination: ShuffleDestination::NewPlaylist }
+                    },
+                    ShuffleStage::Completed(ref details) => rsx! {
+                        ShuffleCompleteView { details: (**details).clone() }
+                    },
+                    ShuffleStage::Error(ref error) => rsx! {
+                        ShuffleErrorView { error: error.clone(), on_retry: move |_| stage.set(ShuffleStage::Idle) }
+                    },
+                }
+            }
+        }
+    }
+}
@@ -1,29 +1,44 @@
-use std::{env, sync::RwLock};
 use anyhow::Result;
-use axum::{response::Redirect, routing::get, extract::State as AxumState};
+use axum::{
+    extract::State as AxumState,
+    http::{header::SET_COOKIE, HeaderMap},
+    response::{AppendHeaders, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form,
+};
 use dioxus::prelude::*;
-use dioxus_logger;
 use dotenvy::dotenv;
-use std::{collections::HashMap, sync::{Mutex,Arc}};
-use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use std::sync::Arc;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
-use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
 
-use crate::{api_models::SpotifyTokenResponse, App};
-use crate::auth::pkce;
+use crate::{config::Config, App};
+use crate::auth::{
+    pending::{PendingLoginError, PendingLogins, MAX_PENDING_LOGINS, PENDING_LOGIN_TTL},
+    pkce,
+    session::SessionStore,
+    token::{self, SessionTokens, TokenError},
+};
+use crate::spotify::SpotifyClient;
+use crate::storage::Storage;
 
 
 #[derive(Clone)]
 pub struct AppState{
-    pub pkce_verifiers : Arc<Mutex<HashMap<String, String>>>,
-    pub current_user_tokens: Arc<RwLock<Option<SpotifyTokenResponse>>>
+    pub config: Arc<Config>,
+    pub pending_logins: PendingLogins,
+    pub storage: Storage,
+    pub sessions: SessionStore,
+    pub spotify: SpotifyClient,
 }
 
 impl AppState{
-    pub fn new() -> Self{
+    pub fn new(config: Config, storage: Storage) -> Self{
         Self{
-            pkce_verifiers: Arc::new(Mutex::new(HashMap::new())),
-            current_user_tokens: Arc::new(RwLock::new(None))
+            pending_logins: PendingLogins::new(PENDING_LOGIN_TTL, MAX_PENDING_LOGINS),
+            sessions: SessionStore::new(config.session_secret.clone(), storage.clone()),
+            spotify: SpotifyClient::new(config.spotify.clone()),
+            storage,
+            config: Arc::new(config),
         }
     }
 }
@@ -37,10 +52,16 @@ pub async fn start_server() -> Result<()> {
 
     dioxus_logger::init(tracing::Level::INFO).expect("failed to init logger");
 
-    let _client_id = env::var("SPOTIFY_CLIENT_ID")
-        .expect("SPOTIFY_CLIENT_ID must be set in .env");
+    let config = Config::load()?;
 
-    let app_state = AppState::new();
+    let storage = Storage::open(&config.database_path, config.token_encryption_key)?;
+    tracing::info!("Opened database at {}", config.database_path);
+
+    let app_state = AppState::new(config, storage);
+    let restored = app_state.sessions.restore()?;
+    tracing::info!("Restored {} sessions from storage", restored);
+
+    app_state.pending_logins.spawn_sweeper();
 
     let provider = {
         let shared = app_state.clone();
@@ -52,9 +73,7 @@ pub async fn start_server() -> Result<()> {
 
      let address = dioxus::cli_config::fullstack_address_or_localhost();
 
-    let axum_router = axum::Router::new()
-        .route("/login", get(spotify_login_handler))
-        .route("/callback", get(spotify_callback_handler))
+    let axum_router = auth_routes()
         .serve_dioxus_application(cfg,App)
         .with_state(app_state.clone());
 
@@ -65,14 +84,19 @@ pub async fn start_server() -> Result<()> {
     Ok(())
 }
 
+/// The OAuth and logout endpoints, everything else is served by Dioxus
+pub fn auth_routes() -> axum::Router<AppState>{
+    axum::Router::new()
+        .route("/auth/login", get(spotify_login_handler))
+        .route("/callback", get(spotify_callback_handler))
+        .route("/logout", post(logout_handler))
+}
+
 async fn spotify_login_handler(
     AxumState(app_state):AxumState<AppState>,
-) -> Redirect{
-
-    let client_id = env::var("SPOTIFY_CLIENT_ID")
-        .expect("sptify client id must be set");
-    let redirect_uri = env::var("SPOTIFY_REDIRECT_URI")
-        .expect("Spotify redirect uri must be set");
+    headers: HeaderMap,
+) -> Response{
+    let spotify = &app_state.config.spotify;
 
     //generate pkce codes
     let code_verifier = pkce::generate_code_verifier();
@@ -87,47 +111,53 @@ async fn spotify_login_handler(
 
     //stroing code verifier temporarily
 
-    app_state
-        .pkce_verifiers
-        .lock()
-        .unwrap()
-        .insert(state.clone(), code_verifier);
+    app_state.pending_logins.insert(state.clone(), code_verifier);
     
     tracing::info!("Stored verifier for state:{}",state);
 
-    let scope = "playlist-read-private playlist-read-collaborative playlist-modify-private
-    user-read-private user-read-email ugc-image-upload";
+    let scope = spotify.scopes.join(" ");
 
     // construct URL
 
-    let mut auth_url = reqwest::Url::parse("https://accounts.spotify.com/authorize").unwrap();
+    let mut auth_url = spotify.accounts_url("authorize");
     auth_url.query_pairs_mut()
         .append_pair("response_type","code")
-        .append_pair("client_id", &client_id)
-        .append_pair("scope", scope)
-        .append_pair("redirect_uri", &redirect_uri)
+        .append_pair("client_id", &spotify.client_id)
+        .append_pair("scope", &scope)
+        .append_pair("redirect_uri", &spotify.redirect_uri)
         .append_pair("state", &state)
         .append_pair("code_challenge_method", "S256")
         .append_pair("code_challenge", &code_challenge);
-    tracing::info!("Redirecting user to spotify: {}", auth_url);
 
+    // already logged in means the user wants to link another account, so let them pick one
+    if app_state.sessions.session_id_from_headers(&headers).is_some(){
+        auth_url.query_pairs_mut().append_pair("show_dialog", "true");
+    }
+    tracing::info!("Redirecting user to spotify: {}", auth_url);
 
-    Redirect::temporary(auth_url.as_str())
+    // the callback only accepts this state from the browser holding the cookie
+    let state_cookie = app_state.sessions.login_state_cookie(
+        &state,
+        PENDING_LOGIN_TTL.as_secs(),
+        app_state.config.secure_cookies(),
+    );
+    ([(SET_COOKIE, state_cookie)], Redirect::temporary(auth_url.as_str())).into_response()
 }
 
 
 
 async fn spotify_callback_handler(
     AxumState(app_state):AxumState<AppState>,
+    headers: HeaderMap,
     query: axum::extract::Query<std::collections::HashMap<String,String>>,
-) -> Redirect{
+) -> Response{
     // query will either respond with  code and state, or error and state
     
     let code = match query.get("code"){
         Some(c) => c.clone(),
         None => {
             tracing::error!("Callback missing code param");
-            return Redirect::temporary("/login?error=missing_code");
+            return Redirect::temporary("/login?error=missing_code").into_response();
         }
     };
 
@@ -135,89 +165,366 @@ async fn spotify_callback_handler(
         Some(s) => s.clone(),
         None => {
             tracing::error!("Callback missing state param");
-            return Redirect::temporary("/login?error=missing_state");
+            return Redirect::temporary("/login?error=missing_state").into_response();
         }
     };
 
     // get code verifier and match state, for CSRF protection
 
-    let code_verifier = {
-        let mut verifiers = app_state.pkce_verifiers.lock().unwrap();
-
-        match verifiers.remove(&received_state){
-            Some(v) => v,
-            None => {
-                tracing::error!("state mismatch OR verifier not found for state");
-                return Redirect::temporary("/login?error=state_mismatch")
-            }
+    let code_verifier = match app_state.pending_logins.take(&received_state){
+        Ok(v) => v,
+        Err(PendingLoginError::Expired) => {
+            tracing::warn!("login for state {} expired before the callback", received_state);
+            return Redirect::temporary("/login?error=state_expired").into_response()
+        }
+        Err(PendingLoginError::Unknown) => {
+            tracing::error!("state mismatch OR verifier not found for state");
+            return Redirect::temporary("/login?error=state_mismatch").into_response()
         }
     };
+    // a state that is known but was started from another browser is a login CSRF attempt
+    if !app_state.sessions.login_state_matches(&headers, &received_state){
+        tracing::warn!("callback for state {} came without its login cookie", received_state);
+        return Redirect::temporary("/login?error=state_mismatch").into_response();
+    }
     tracing::info!("Retrieved verifier for state: {}",received_state);
 
-    let client_id = env::var("SPOTIFY_CLIENT_ID").expect("SPOTIFY_CLIENT_ID must be set");
-    let redirect_uri = env::var("SPOTIFY_REDIRECT_URI").expect("SPOTIFY_REDIRECT_URI must be set");
-    let client_secret = env::var("SPOTIFY_CLIENT_SECRET").expect("SPOTIFY_CLIENT_SECRET must be set");
-
-    // prepare token request
-
-    let client = reqwest::Client::new();
-    let token_endpoint = "https://accounts.spotify.com/api/token";
+    tracing::info!("Requesting Access Token");
 
-    let params = [
+    let token_result = token::request_token(&app_state.spotify, &[
         ("grant_type", "authorization_code"),
         ("code", &code),
-        ("redirect_uri",&redirect_uri),
-        ("client_id",&client_id),
+        ("redirect_uri",&app_state.config.spotify.redirect_uri),
         ("code_verifier",&code_verifier),
-    ];
+    ]).await;
 
-    let auth_header_value = format!(
-        "Basic {}",
-        URL_SAFE_NO_PAD.encode(format!("{}:{}", client_id,client_secret))
-    );
+    match token_result{
+        Ok(token_response) =>{
+            tracing::info!("Succesfully obtained tokens for a new session");
+            let tokens = SessionTokens::from_response(token_response, None);
+
+            // the Spotify user id is what ties stored tokens and sessions together
+            let profile = match app_state.spotify.me_with_token(&tokens.access_token).await{
+                Ok(profile) => profile,
+                Err(e) => {
+                    tracing::error!("Failed to fetch profile for new session: {}", e);
+                    return Redirect::temporary("/login?error=profile_fetch_failed").into_response();
+                }
+            };
 
-    tracing::info!("Requesting Access Token");
+            let user = match app_state.storage.upsert_user(&profile.id, &profile.display_name){
+                Ok(user) => user,
+                Err(e) => {
+                    tracing::error!("Failed to store user {}: {}", profile.id, e);
+                    return Redirect::temporary("/login?error=storage_error").into_response();
+                }
+            };
+
+            // logging in from a browser that already has a session links the account to it
+            let existing_session = app_state.sessions.session_id_from_headers(&headers);
+            let session_id = match app_state.sessions.login(existing_session, user, tokens){
+                Ok(session_id) => session_id,
+                Err(e) => {
+                    tracing::error!("Failed to store session: {}", e);
+                    return Redirect::temporary("/login?error=storage_error").into_response();
+                }
+            };
+            let secure = app_state.config.secure_cookies();
+            let cookies = AppendHeaders([
+                (SET_COOKIE, app_state.sessions.cookie_for(&session_id, secure)),
+                (SET_COOKIE, app_state.sessions.expired_login_state_cookie(&received_state, secure)),
+            ]);
+
+            (cookies, Redirect::temporary("/")).into_response()
+        }
+        Err(TokenError::Parse(e)) => {
+            tracing::error!( "failed to parse token response json:{}",e);
+            Redirect::temporary("/login?error=token_parse_failed").into_response()
+        }
+        Err(TokenError::Network(e)) =>{
+            tracing::error!("Failed to send token request: {}", e);
+            Redirect::temporary("/login?error=network_error").into_response()
+        }
+        Err(e) => {
+            tracing::error!("Token request failed: {}", e);
+            Redirect::temporary("/login?error=token_request_failed").into_response()
+        }
+    }
+}
 
-    let response_result = client
-        .post(token_endpoint)
-        .header(AUTHORIZATION, auth_header_value)
-        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
-        .header(ACCEPT, "application/json")
-        .form(&params)
-        .send()
-        .await;
+#[derive(serde::Deserialize)]
+struct LogoutForm{
+    account: Option<String>,
+}
 
-    match response_result{
-        Ok(token_response) =>{
-            if token_response.status().is_success(){
-                match token_response.json::<SpotifyTokenResponse>().await{
-                    Ok(token_reponse) =>{
-                        tracing::info!("Succesfully obtained tokens: {:?}", token_reponse);
+/// Logs out one linked account (`account` = Spotify user id) or the whole session.
+/// Spotify has no endpoint to revoke a user's grant, so forgetting our copy of the
+/// tokens is all we can do; users can still revoke the app from their Spotify account page.
+async fn logout_handler(
+    AxumState(app_state):AxumState<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<LogoutForm>,
+) -> Response{
+    let clear_cookie = [(SET_COOKIE, app_state.sessions.expired_cookie(app_state.config.secure_cookies()))];
 
-                        //TODO Change AS its only for solo dev
+    let Some(session_id) = app_state.sessions.session_id_from_headers(&headers) else {
+        return (clear_cookie, Redirect::to("/login")).into_response();
+    };
 
-                        *app_state.current_user_tokens.write().unwrap() = Some(token_reponse);
+    match form.account{
+        Some(spotify_id) => {
+            let account = app_state
+                .sessions
+                .status(&session_id)
+                .and_then(|(_, linked, _)| linked.into_iter().find(|user| user.spotify_id == spotify_id));
+            // a stale account menu can name an account that is already gone, that logs out nobody
+            let Some(user) = account else {
+                tracing::warn!("Logout asked for account {} which isn't linked to the session", spotify_id);
+                return Redirect::to("/").into_response();
+            };
+            tracing::info!("Logging out account {}", user.spotify_id);
+            if app_state.sessions.remove_account(&session_id, user.id){
+                return Redirect::to("/").into_response();
+            }
+        }
+        None => {
+            tracing::info!("Logging out session");
+            app_state.sessions.remove(&session_id);
+        }
+    }
 
-                        Redirect::temporary("/")
-                    }
+    (clear_cookie, Redirect::to("/login")).into_response()
+}
 
-                    Err(e) => {
-                        tracing::error!( "failed to parse token response json:{}",e);
-                        Redirect::temporary("/login?error=token_parse_failed")
-                    }
-                }
-            }else{
-                let status = token_response.status();
-                let text = token_response.text().await.unwrap_or_else(|_| {
-                    "Failed to read error body".to_string()
-                });
-                tracing::error!("Token request failed with status {}:{}", status,text);
-                Redirect::temporary("/login?error=token_request_failed")
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use axum::http::{header::{COOKIE, LOCATION}, Method, StatusCode};
+    use crate::auth::session::SESSION_COOKIE;
+    use crate::test_support::{test_app_state, test_config, FakeSpotify};
+
+    struct TestApp{
+        base_url: String,
+        app_state: AppState,
+        client: reqwest::Client,
+    }
+
+    impl TestApp{
+        async fn start(fake: &FakeSpotify) -> Self{
+            Self::start_with(fake, PendingLogins::new(PENDING_LOGIN_TTL, MAX_PENDING_LOGINS)).await
+        }
+
+        async fn start_with(fake: &FakeSpotify, pending_logins: PendingLogins) -> Self{
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let base_url = format!("http://{}", listener.local_addr().unwrap());
+            let mut app_state = test_app_state(test_config(fake, &format!("{}/callback", base_url)));
+            app_state.pending_logins = pending_logins;
+
+            let router = auth_routes().with_state(app_state.clone());
+            tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+            let client = reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .unwrap();
+            Self{ base_url, app_state, client }
+        }
+
+        /// GETs `url` and returns where it redirects to, plus the session cookie it set
+        async fn follow(&self, url: &str, cookie: Option<&str>) -> (String, Option<String>){
+            let (location, cookies) = self.follow_with_cookies(url, cookie).await;
+            let session = cookies.into_iter().find(|cookie| cookie.starts_with(&format!("{}=", SESSION_COOKIE)));
+            (location, session)
+        }
+
+        /// GETs `url` and returns where it redirects to, plus every `name=value` cookie it set
+        async fn follow_with_cookies(&self, url: &str, cookie: Option<&str>) -> (String, Vec<String>){
+            let mut request = self.client.get(url);
+            if let Some(cookie) = cookie{
+                request = request.header(COOKIE, cookie);
             }
+            let response = request.send().await.unwrap();
+            assert!(response.status().is_redirection(), "{} answered {}", url, response.status());
+            let location = response.headers()[LOCATION].to_str().unwrap().to_string();
+            let cookies = response
+                .headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .map(|value| value.to_str().unwrap().split(';').next().unwrap().to_string())
+                .collect();
+            (location, cookies)
         }
-        Err(e) =>{
-            tracing::error!("Failed to send token request: {}", e);
-            Redirect::temporary("/login?error=network_error")
+
+        /// Runs the whole login dance and returns the callback's redirect and cookie
+        async fn log_in(&self, cookie: Option<&str>) -> (String, Option<String>){
+            let (authorize_url, login_cookies) = self
+                .follow_with_cookies(&format!("{}/auth/login", self.base_url), cookie)
+                .await;
+            let (callback_url, _) = self.follow(&authorize_url, None).await;
+            let cookies = cookie.map(str::to_string).into_iter().chain(login_cookies).collect::<Vec<_>>().join("; ");
+            self.follow(&callback_url, Some(&cookies)).await
+        }
+
+        fn session_user(&self, cookie: &str) -> Option<String>{
+            let mut headers = HeaderMap::new();
+            headers.insert(COOKIE, cookie.parse().unwrap());
+            let session_id = self.app_state.sessions.session_id_from_headers(&headers)?;
+            self.app_state.sessions.status(&session_id).map(|(active, _, _)| active.spotify_id)
+        }
+    }
+
+    #[tokio::test]
+    async fn callback_creates_a_session_for_the_spotify_user(){
+        let fake = FakeSpotify::start().await;
+        fake.add_user("alice", "Alice");
+        fake.login_as("alice");
+        let app = TestApp::start(&fake).await;
+
+        let (location, cookie) = app.log_in(None).await;
+
+        assert_eq!(location, "/");
+        let cookie = cookie.expect("callback sets the session cookie");
+        assert_eq!(app.session_user(&cookie).as_deref(), Some("alice"));
+    }
+
+    #[tokio::test]
+    async fn second_login_links_another_account_to_the_session(){
+        let fake = FakeSpotify::start().await;
+        fake.add_user("alice", "Alice");
+        fake.add_user("bob", "Bob");
+        fake.login_as("alice");
+        let app = TestApp::start(&fake).await;
+        let (_, cookie) = app.log_in(None).await;
+        let cookie = cookie.unwrap();
+
+        fake.login_as("bob");
+        let (location, relinked) = app.log_in(Some(&cookie)).await;
+
+        assert_eq!(location, "/");
+        assert_eq!(relinked.as_deref(), Some(cookie.as_str()));
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, cookie.parse().unwrap());
+        let session_id = app.app_state.sessions.session_id_from_headers(&headers).unwrap();
+        let (active, linked, _) = app.app_state.sessions.status(&session_id).unwrap();
+        assert_eq!(active.spotify_id, "bob");
+        assert_eq!(linked.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn logging_out_an_account_that_is_no_longer_linked_keeps_the_session(){
+        let fake = FakeSpotify::start().await;
+        fake.add_user("alice", "Alice");
+        fake.login_as("alice");
+        let app = TestApp::start(&fake).await;
+        let (_, cookie) = app.log_in(None).await;
+        let cookie = cookie.unwrap();
+
+        let response = app
+            .client
+            .post(format!("{}/logout", app.base_url))
+            .header(COOKIE, &cookie)
+            .form(&[("account", "bob")])
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers()[LOCATION], "/");
+        assert!(response.headers().get(SET_COOKIE).is_none());
+        assert_eq!(app.session_user(&cookie).as_deref(), Some("alice"));
+    }
+
+    #[tokio::test]
+    async fn callback_rejects_an_unknown_state(){
+        let fake = FakeSpotify::start().await;
+        let app = TestApp::start(&fake).await;
+
+        let (location, cookie) = app.follow(&format!("{}/callback?code=abc&state=forged", app.base_url), None).await;
+
+        assert_eq!(location, "/login?error=state_mismatch");
+        assert!(cookie.is_none());
+    }
+
+    #[tokio::test]
+    async fn callback_rejects_a_state_started_in_another_browser(){
+        let fake = FakeSpotify::start().await;
+        fake.add_user("mallory", "Mallory");
+        fake.login_as("mallory");
+        let app = TestApp::start(&fake).await;
+
+        // the attacker starts a login and hands the callback link to someone else
+        let (authorize_url, _) = app.follow(&format!("{}/auth/login", app.base_url), None).await;
+        let (callback_url, _) = app.follow(&authorize_url, None).await;
+        let (location, cookie) = app.follow(&callback_url, None).await;
+
+        assert_eq!(location, "/login?error=state_mismatch");
+        assert!(cookie.is_none());
+        assert_eq!(fake.count("POST", "/api/token"), 0);
+    }
+
+    #[tokio::test]
+    async fn callback_reports_an_expired_login(){
+        let fake = FakeSpotify::start().await;
+        fake.add_user("alice", "Alice");
+        fake.login_as("alice");
+        let app = TestApp::start_with(&fake, PendingLogins::new(std::time::Duration::from_millis(50), 8)).await;
+
+        let (authorize_url, _) = app.follow(&format!("{}/auth/login", app.base_url), None).await;
+        let (callback_url, _) = app.follow(&authorize_url, None).await;
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        // the browser has dropped the state cookie by now, it lives as long as the login
+        let (location, cookie) = app.follow(&callback_url, None).await;
+
+        assert_eq!(location, "/login?error=state_expired");
+        assert!(cookie.is_none());
+    }
+
+    #[tokio::test]
+    async fn logins_started_in_two_tabs_both_complete(){
+        let fake = FakeSpotify::start().await;
+        fake.add_user("alice", "Alice");
+        fake.login_as("alice");
+        let app = TestApp::start(&fake).await;
+
+        let (first_authorize, first_cookies) = app.follow_with_cookies(&format!("{}/auth/login", app.base_url), None).await;
+        let (_, second_cookies) = app.follow_with_cookies(&format!("{}/auth/login", app.base_url), None).await;
+        let (callback_url, _) = app.follow(&first_authorize, None).await;
+        // what a cookie jar would send: a later cookie of the same name replaces the earlier one
+        let mut jar = std::collections::HashMap::new();
+        for cookie in first_cookies.iter().chain(&second_cookies) {
+            let (name, value) = cookie.split_once('=').unwrap();
+            jar.insert(name.to_string(), value.to_string());
         }
+        let jar: Vec<String> = jar.into_iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        let (location, cookie) = app.follow(&callback_url, Some(&jar.join("; "))).await;
+
+        assert_eq!(location, "/");
+        assert!(cookie.is_some());
+    }
+
+    #[tokio::test]
+    async fn callback_reports_a_failing_token_endpoint(){
+        let fake = FakeSpotify::start().await;
+        fake.add_user("alice", "Alice");
+        fake.login_as("alice");
+        fake.fail(Method::POST, "/api/token", StatusCode::INTERNAL_SERVER_ERROR, 1);
+        let app = TestApp::start(&fake).await;
+
+        let (location, cookie) = app.log_in(None).await;
+
+        assert_eq!(location, "/login?error=token_request_failed");
+        assert!(cookie.is_none());
+    }
+
+    #[tokio::test]
+    async fn callback_reports_a_failing_profile_fetch(){
+        let fake = FakeSpotify::start().await;
+        fake.add_user("alice", "Alice");
+        fake.login_as("alice");
+        fake.fail(Method::GET, "/v1/me", StatusCode::SERVICE_UNAVAILABLE, 10);
+        let app = TestApp::start(&fake).await;
+
+        let (location, _) = app.log_in(None).await;
+
+        assert_eq!(location, "/login?error=profile_fetch_failed");
     }
 }
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use rand::{seq::SliceRandom, RngCore};
+
+use super::ShuffleStrategy;
+use crate::api_models::SpotifyTrackItem;
+
+/// Shuffles whole albums (or podcast shows), each keeping its tracks in playlist order
+pub struct AlbumBlocks;
+
+impl ShuffleStrategy for AlbumBlocks {
+    fn name(&self) -> &'static str {
+        "albums"
+    }
+
+    fn label(&self) -> &'static str {
+        "Album blocks"
+    }
+
+    fn description(&self) -> &'static str {
+        "Albums play in a random order, the tracks of each album stay together."
+    }
+
+    fn shuffle(&self, tracks: Vec<SpotifyTrackItem>, rng: &mut dyn RngCore) -> Vec<SpotifyTrackItem> {
+        // blocks in order of first appearance so the result only depends on the rng
+        let mut blocks: Vec<Vec<SpotifyTrackItem>> = Vec::new();
+        let mut block_of_album: HashMap<String, usize> = HashMap::new();
+        for track in tracks {
+            let album_id = track.album.as_ref().and_then(|album| album.id.clone());
+            match album_id {
+                Some(id) if block_of_album.contains_key(&id) => blocks[block_of_album[&id]].push(track),
+                Some(id) => {
+                    block_of_album.insert(id, blocks.len());
+                    blocks.push(vec![track]);
+                }
+                // no album id (local files): a block of its own
+                None => blocks.push(vec![track]),
+            }
+        }
+        blocks.shuffle(rng);
+        blocks.into_iter().flatten().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::track_item;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn albums_stay_together_in_playlist_order() {
+        let tracks = vec![track_item("a1", "x", "a"), track_item("b1", "x", "b"), track_item("a2", "x", "a"), track_item("c1", "x", "c"), track_item("b2", "x", "b")];
+
+        let shuffled = AlbumBlocks.shuffle(tracks, &mut StdRng::seed_from_u64(7));
+
+        let ids: Vec<&str> = shuffled.iter().map(|t| t.id.as_deref().unwrap()).collect();
+        let position = |id| ids.iter().position(|i| *i == id).unwrap();
+        assert_eq!(ids.len(), 5);
+        assert_eq!(position("a2"), position("a1") + 1);
+        assert_eq!(position("b2"), position("b1") + 1);
+    }
+}
@@ -0,0 +1,80 @@
+//! Combining several playlists into the tracks of one mix, before a strategy orders them.
+
+use std::collections::HashSet;
+
+use rand::{seq::SliceRandom, RngCore};
+
+use crate::api_models::SpotifyTrackItem;
+
+/// Drops every track already seen, earlier in the same source or in an earlier one, and
+/// returns how many each source lost. Tracks are the same when their ids are, or their URIs
+/// for items without an id (local files).
+pub fn dedupe(sources: &mut [Vec<SpotifyTrackItem>]) -> Vec<u32> {
+    let mut seen: HashSet<String> = HashSet::new();
+    sources
+        .iter_mut()
+        .map(|tracks| {
+            let before = tracks.len();
+            tracks.retain(|track| seen.insert(track.id.clone().unwrap_or_else(|| track.uri.clone())));
+            (before - tracks.len()) as u32
+        })
+        .collect()
+}
+
+/// Picks tracks at random from each source so that source `i` makes up `weights[i]` of
+/// the total, taking as many as the source that runs out first allows. Every weight must
+/// be above 0, and every source hold a track: an empty one leaves room for none at all.
+pub fn weigh(sources: Vec<Vec<SpotifyTrackItem>>, weights: &[u32], rng: &mut dyn RngCore) -> Vec<Vec<SpotifyTrackItem>> {
+    let weight_sum: u64 = weights.iter().map(|weight| *weight as u64).sum();
+    // the largest total every source can fill its share of
+    let total = sources
+        .iter()
+        .zip(weights)
+        .map(|(tracks, weight)| tracks.len() as u64 * weight_sum / *weight as u64)
+        .min()
+        .unwrap_or(0);
+
+    sources
+        .into_iter()
+        .zip(weights)
+        .map(|(mut tracks, weight)| {
+            tracks.shuffle(rng);
+            tracks.truncate((total * *weight as u64 / weight_sum) as usize);
+            tracks
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::track_item;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn source(prefix: &str, n: usize) -> Vec<SpotifyTrackItem> {
+        (0..n).map(|i| track_item(&format!("{}{}", prefix, i), prefix, prefix)).collect()
+    }
+
+    #[test]
+    fn dedupe_keeps_the_first_copy() {
+        let mut a = source("a", 3);
+        a.push(a[0].clone());
+        let mut b = source("b", 2);
+        b.push(a[1].clone());
+        let mut sources = vec![a, b];
+
+        assert_eq!(dedupe(&mut sources), [1, 1]);
+        assert_eq!(sources.iter().map(Vec::len).collect::<Vec<_>>(), [3, 2]);
+    }
+
+    #[test]
+    fn weights_set_the_share_of_each_source() {
+        let sources = vec![source("a", 40), source("b", 20), source("c", 15)];
+
+        let weighed = weigh(sources, &[50, 25, 25], &mut StdRng::seed_from_u64(1));
+
+        // c runs out first: 15 tracks are its quarter of 60
+        assert_eq!(weighed.iter().map(Vec::len).collect::<Vec<_>>(), [30, 15, 15]);
+        assert!(weighed[0].iter().all(|track| track.uri.starts_with("spotify:track:a")));
+    }
+}
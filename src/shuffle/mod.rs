@@ -0,0 +1,132 @@
+//! The orderings a shuffle can produce.
+//!
+//! Every strategy turns a playlist's items into a new order using the rng it is handed, so
+//! the caller decides where the randomness comes from. [`STRATEGIES`] is the registry the
+//! UI lists and `shuffle_and_save_new_playlist` looks strategies up in by name.
+
+mod albums;
+pub mod merge;
+mod spread;
+mod uniform;
+
+use std::collections::HashMap;
+
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::api_models::{ShuffleStrategyInfo, ShuffleSummary, SpotifyTrackItem};
+
+pub use albums::AlbumBlocks;
+pub use spread::Spread;
+pub use uniform::Uniform;
+
+pub trait ShuffleStrategy: Sync {
+    /// Stable id passed between the UI and the server fns
+    fn name(&self) -> &'static str;
+    fn label(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    fn shuffle(&self, tracks: Vec<SpotifyTrackItem>, rng: &mut dyn RngCore) -> Vec<SpotifyTrackItem>;
+
+    fn info(&self) -> ShuffleStrategyInfo {
+        ShuffleStrategyInfo {
+            name: self.name().to_string(),
+            label: self.label().to_string(),
+            description: self.description().to_string(),
+        }
+    }
+}
+
+/// Every strategy, the first one is the default
+pub static STRATEGIES: &[&dyn ShuffleStrategy] = &[&Uniform, &Spread { albums: false }, &Spread { albums: true }, &AlbumBlocks];
+
+pub fn find(name: &str) -> Option<&'static dyn ShuffleStrategy> {
+    STRATEGIES.iter().copied().find(|strategy| strategy.name() == name)
+}
+
+/// Who a track counts as being by: its first artist (the show, for episodes)
+pub fn artist_key(track: &SpotifyTrackItem) -> Option<String> {
+    // an episode's artists aren't always filled in, the show it is listed under as album is
+    if track.is_episode() {
+        return album_key(track);
+    }
+    let artist = track.artists.first()?;
+    artist.id.clone().or_else(|| Some(artist.name.to_lowercase()))
+}
+
+pub fn album_key(track: &SpotifyTrackItem) -> Option<String> {
+    let album = track.album.as_ref()?;
+    album.id.clone().or_else(|| Some(album.name.to_lowercase()))
+}
+
+/// The smallest distance between two tracks with the same key, 1 being back to back.
+/// `None` when no key repeats.
+pub fn min_gap(tracks: &[SpotifyTrackItem], key: fn(&SpotifyTrackItem) -> Option<String>) -> Option<u32> {
+    let mut last_seen: HashMap<String, usize> = HashMap::new();
+    let mut smallest: Option<usize> = None;
+    for (i, track) in tracks.iter().enumerate() {
+        let Some(key) = key(track) else { continue };
+        if let Some(previous) = last_seen.insert(key, i) {
+            smallest = Some(smallest.map_or(i - previous, |gap| gap.min(i - previous)));
+        }
+    }
+    smallest.map(|gap| gap as u32)
+}
+
+/// The rng behind a seeded shuffle. ChaCha rather than `StdRng`, whose algorithm may change
+/// between rand releases and would quietly change what a shared seed means.
+pub fn seeded_rng(seed: u64) -> ChaCha8Rng {
+    ChaCha8Rng::seed_from_u64(seed)
+}
+
+/// How an order came about and how well it keeps artists and albums apart
+pub fn summarize(strategy: &dyn ShuffleStrategy, tracks: &[SpotifyTrackItem], seed: u64, source_snapshot_id: &str) -> ShuffleSummary {
+    ShuffleSummary {
+        strategy: strategy.name().to_string(),
+        seed,
+        source_snapshot_id: source_snapshot_id.to_string(),
+        min_artist_gap: min_gap(tracks, artist_key),
+        min_album_gap: min_gap(tracks, album_key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strategy_names_are_unique() {
+        for strategy in STRATEGIES {
+            assert!(std::ptr::addr_eq(find(strategy.name()).unwrap(), *strategy), "{} is registered twice", strategy.name());
+        }
+        assert_eq!(STRATEGIES[0].name(), "uniform", "uniform stays the default");
+    }
+
+    #[test]
+    fn min_gap_is_the_closest_repeat() {
+        use crate::test_support::track_item;
+
+        let tracks = vec![
+            track_item("1", "a", "x"),
+            track_item("2", "b", "x"),
+            track_item("3", "c", "y"),
+            track_item("4", "a", "y"),
+            track_item("5", "b", "z"),
+        ];
+
+        assert_eq!(min_gap(&tracks, artist_key), Some(3));
+        assert_eq!(min_gap(&tracks, album_key), Some(1));
+        assert_eq!(min_gap(&tracks[..3], artist_key), None);
+    }
+
+    #[test]
+    fn episodes_count_as_by_their_show() {
+        use crate::test_support::track_item;
+
+        let mut episode = track_item("e1", "host", "news-show");
+        episode.item_type = Some("episode".to_string());
+        episode.artists.clear();
+
+        assert_eq!(artist_key(&episode).as_deref(), Some("news-show"));
+        assert_eq!(artist_key(&track_item("1", "a", "x")).as_deref(), Some("a"));
+    }
+}
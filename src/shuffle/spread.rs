@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use rand::{seq::SliceRandom, Rng, RngCore};
+
+use super::{album_key, artist_key, ShuffleStrategy};
+use crate::api_models::SpotifyTrackItem;
+
+/// Keeps tracks by the same artist (and with `albums`, from the same album) far apart while
+/// staying random.
+///
+/// Each artist's tracks are dithered evenly over the playlist: n tracks land near
+/// `(offset + i) / n` with a random offset and a little jitter, and everything is sorted by
+/// position. Artists with few tracks fall wherever, so the order is then walked again taking
+/// the earliest track that doesn't repeat the previous artist and leaves the rest arrangeable.
+/// With `albums`, each artist's own tracks get the same treatment by album first.
+pub struct Spread {
+    pub albums: bool,
+}
+
+/// How far a track may wander from its even spot, as a fraction of its artist's spacing
+const JITTER: f64 = 0.2;
+
+impl ShuffleStrategy for Spread {
+    fn name(&self) -> &'static str {
+        if self.albums { "spread-albums" } else { "spread" }
+    }
+
+    fn label(&self) -> &'static str {
+        if self.albums { "Artist and album spread" } else { "Artist spread" }
+    }
+
+    fn description(&self) -> &'static str {
+        if self.albums {
+            "Random, but songs by the same artist, and from the same album, are kept as far apart as possible."
+        } else {
+            "Random, but songs by the same artist are kept as far apart as possible."
+        }
+    }
+
+    fn shuffle(&self, tracks: Vec<SpotifyTrackItem>, rng: &mut dyn RngCore) -> Vec<SpotifyTrackItem> {
+        let mut placed: Vec<(f64, SpotifyTrackItem)> = Vec::with_capacity(tracks.len());
+        for mut group in group_by(tracks, artist_key) {
+            if self.albums {
+                let by_album = dither(group_by(group, album_key), rng).into_iter().map(|(_, track)| track).collect();
+                group = no_twice_in_a_row(by_album, album_key);
+            } else {
+                group.shuffle(rng);
+            }
+            placed.extend(dither(vec![group], rng));
+        }
+        placed.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        no_twice_in_a_row(placed.into_iter().map(|(_, track)| track).collect(), artist_key)
+    }
+}
+
+/// Tracks with the same key, in order of first appearance. Tracks without a key are each their own group.
+fn group_by(tracks: Vec<SpotifyTrackItem>, key: fn(&SpotifyTrackItem) -> Option<String>) -> Vec<Vec<SpotifyTrackItem>> {
+    let mut groups: Vec<Vec<SpotifyTrackItem>> = Vec::new();
+    let mut group_of: HashMap<String, usize> = HashMap::new();
+    for track in tracks {
+        match key(&track) {
+            Some(key) if group_of.contains_key(&key) => groups[group_of[&key]].push(track),
+            Some(key) => {
+                group_of.insert(key, groups.len());
+                groups.push(vec![track]);
+            }
+            None => groups.push(vec![track]),
+        }
+    }
+    groups
+}
+
+/// Gives every track a position in `[0, 1)`, each group's tracks evenly spaced in their current order
+fn dither(groups: Vec<Vec<SpotifyTrackItem>>, rng: &mut dyn RngCore) -> Vec<(f64, SpotifyTrackItem)> {
+    let mut placed = Vec::new();
+    for group in groups {
+        let spacing = 1.0 / group.len() as f64;
+        let offset = rng.gen_range(0.0..spacing);
+        for (i, track) in group.into_iter().enumerate() {
+            let jitter = rng.gen_range(-JITTER..=JITTER) * spacing;
+            placed.push((offset + i as f64 * spacing + jitter, track));
+        }
+    }
+    placed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    placed
+}
+
+/// Rebuilds `order` keeping it as close as possible while never putting two tracks with the same
+/// `key` back to back, unless one key has more than half the tracks and that can't be helped
+fn no_twice_in_a_row(order: Vec<SpotifyTrackItem>, key: fn(&SpotifyTrackItem) -> Option<String>) -> Vec<SpotifyTrackItem> {
+    // keys as indexes, tracks without one never clash
+    let mut index_of: HashMap<String, usize> = HashMap::new();
+    let labels: Vec<Option<usize>> = order
+        .iter()
+        .map(|track| {
+            let key = key(track)?;
+            let next = index_of.len();
+            Some(*index_of.entry(key).or_insert(next))
+        })
+        .collect();
+    let mut remaining = vec![0usize; index_of.len()];
+    for label in labels.iter().flatten() {
+        remaining[*label] += 1;
+    }
+    // how many labels have each remaining count, so the largest is known without a scan
+    let mut with_count = vec![0usize; order.len() + 1];
+    for count in &remaining {
+        with_count[*count] += 1;
+    }
+    let mut most = remaining.iter().copied().max().unwrap_or(0);
+
+    let mut left: Vec<Option<SpotifyTrackItem>> = order.into_iter().map(Some).collect();
+    let mut result = Vec::with_capacity(left.len());
+    let mut previous: Option<usize> = None;
+    // everything before this has been taken
+    let mut first_left = 0;
+
+    for slots_left in (1..=left.len()).rev() {
+        while left[first_left].is_none() {
+            first_left += 1;
+        }
+        let differs = |i: usize| labels[i].is_none() || labels[i] != previous;
+        let leaves_arrangeable = |i: usize| {
+            let after = slots_left - 1;
+            let Some(label) = labels[i] else {
+                return most <= after.div_ceil(2);
+            };
+            let placed_count = remaining[label] - 1;
+            let most_after = if remaining[label] == most && with_count[most] == 1 { most - 1 } else { most };
+            // the key just placed can't open the rest, so it may only fill every other slot after it
+            most_after <= after.div_ceil(2) && !(after % 2 == 1 && placed_count == after.div_ceil(2))
+        };
+        let candidates = || (first_left..left.len()).filter(|i| left[*i].is_some());
+
+        // only the previous key left: nothing to choose, and searching would make this quadratic
+        let index = if previous.is_some_and(|label| remaining[label] == slots_left) {
+            Some(first_left)
+        } else {
+            candidates()
+                .find(|i| differs(*i) && leaves_arrangeable(*i))
+                .or_else(|| candidates().find(|i| differs(*i)))
+        }
+        .or_else(|| candidates().next())
+        .expect("a track is left for every slot");
+        if let Some(label) = labels[index] {
+            with_count[remaining[label]] -= 1;
+            remaining[label] -= 1;
+            with_count[remaining[label]] += 1;
+            while most > 0 && with_count[most] == 0 {
+                most -= 1;
+            }
+        }
+        previous = labels[index];
+        result.push(left[index].take().expect("candidates are taken once"));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shuffle::min_gap;
+    use crate::test_support::track_item;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn ids(tracks: &[SpotifyTrackItem]) -> Vec<String> {
+        let mut ids: Vec<String> = tracks.iter().map(|t| t.uri.clone()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn a_dominant_artist_never_plays_back_to_back() {
+        let mut tracks: Vec<SpotifyTrackItem> = (0..10).map(|i| track_item(&format!("a{}", i), "a", "a-album")).collect();
+        tracks.extend((0..12).map(|i| track_item(&format!("o{}", i), &format!("other{}", i % 4), "o-album")));
+
+        for seed in 0..50 {
+            let shuffled = Spread { albums: false }.shuffle(tracks.clone(), &mut StdRng::seed_from_u64(seed));
+
+            assert_eq!(ids(&shuffled), ids(&tracks));
+            assert!(min_gap(&shuffled, artist_key).unwrap() >= 2, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn albums_are_spread_within_an_artist() {
+        let mut tracks: Vec<SpotifyTrackItem> = (0..6).map(|i| track_item(&format!("x{}", i), "a", "x")).collect();
+        tracks.extend((0..6).map(|i| track_item(&format!("y{}", i), "a", "y")));
+
+        for seed in 0..50 {
+            let shuffled = Spread { albums: true }.shuffle(tracks.clone(), &mut StdRng::seed_from_u64(seed));
+
+            assert_eq!(ids(&shuffled), ids(&tracks));
+            assert!(min_gap(&shuffled, album_key).unwrap() >= 2, "seed {}", seed);
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use rand::{seq::SliceRandom, RngCore};
+
+use super::ShuffleStrategy;
+use crate::api_models::SpotifyTrackItem;
+
+/// Fisher–Yates: every order is equally likely
+pub struct Uniform;
+
+impl ShuffleStrategy for Uniform {
+    fn name(&self) -> &'static str {
+        "uniform"
+    }
+
+    fn label(&self) -> &'static str {
+        "True random"
+    }
+
+    fn description(&self) -> &'static str {
+        "Every order is equally likely, no favourites."
+    }
+
+    fn shuffle(&self, mut tracks: Vec<SpotifyTrackItem>, rng: &mut dyn RngCore) -> Vec<SpotifyTrackItem> {
+        tracks.shuffle(rng);
+        tracks
+    }
+}
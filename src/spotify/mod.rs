@@ -0,0 +1,647 @@
+//! Typed access to the Spotify Web API.
+//!
+//! Every call goes through [`SpotifyClient::send`], which attaches the caller's access token,
+//! refreshes it once on a 401, retries rate limits and transient failures (see [`RetryPolicy`]),
+//! turns non-2xx answers into [`SpotifyError`] and logs failures.
+
+pub mod paging;
+pub mod retry;
+
+use std::{fmt, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use reqwest::{header::CONTENT_TYPE, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use futures::Stream;
+
+use crate::api_models::{
+    PlaylistItemTrackWrapper, SpotifyPlaylistItem, SpotifyPlaylistTrackResponse, SpotifyPlaylistsResponse, SpotifyUserProfile,
+};
+use crate::auth::token::{self, TokenError};
+use crate::config::SpotifyConfig;
+use crate::server::AppState;
+use retry::{Retry, RetryPolicy};
+
+/// Everything [`PlaylistItemTrackWrapper`] models except `available_markets`, which is left out
+/// by asking for the caller's market (and would add ~180 codes per track otherwise)
+const PLAYLIST_TRACK_FIELDS: &str = "items(added_at,added_by.id,is_local,track(id,name,uri,type,duration_ms,explicit,is_local,popularity,is_playable,\
+artists(id,name),album(id,name,images,release_date,release_date_precision))),limit,offset,total,next,previous";
+/// Largest page `/me/playlists` hands out
+const PLAYLISTS_PAGE_LIMIT: u32 = 50;
+/// Largest page of playlist items Spotify hands out
+const PLAYLIST_TRACKS_PAGE_LIMIT: u32 = 100;
+/// Largest page of saved tracks `/me/tracks` hands out
+const SAVED_TRACKS_PAGE_LIMIT: u32 = 50;
+/// Pages requested at once when the total is known up front
+const PAGE_FETCH_CONCURRENCY: usize = 4;
+
+#[derive(Debug)]
+pub enum SpotifyError {
+    Auth(TokenError),
+    Network(reqwest::Error),
+    Api { status: StatusCode, message: String },
+    /// Still rate limited once the retry budget ran out
+    RateLimited { retry_after: Option<Duration> },
+    Parse(reqwest::Error),
+    Pagination(String),
+}
+
+impl fmt::Display for SpotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpotifyError::Auth(e) => write!(f, "{}", e),
+            SpotifyError::Network(e) => write!(f, "Network Error: {}", e),
+            SpotifyError::Api { status, message } => write!(f, "Spotify API Error ({}): {}", status, message),
+            SpotifyError::RateLimited { retry_after: Some(wait) } => write!(f, "Rate limited by Spotify, retry after {:?}", wait),
+            SpotifyError::RateLimited { retry_after: None } => write!(f, "Rate limited by Spotify"),
+            SpotifyError::Parse(e) => write!(f, "Failed to parse Spotify response: {}", e),
+            SpotifyError::Pagination(message) => write!(f, "Failed to follow Spotify pagination: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SpotifyError {}
+
+impl From<TokenError> for SpotifyError {
+    fn from(e: TokenError) -> Self {
+        SpotifyError::Auth(e)
+    }
+}
+
+/// The session a request is made on behalf of
+#[derive(Clone)]
+pub struct Caller {
+    app_state: AppState,
+    session_id: String,
+}
+
+impl Caller {
+    pub fn new(app_state: AppState, session_id: String) -> Self {
+        Self { app_state, session_id }
+    }
+
+    /// The caller's access token, refreshed if needed. Never hand this to the browser.
+    async fn access_token(&self) -> Result<String, TokenError> {
+        token::access_token(&self.app_state, &self.session_id).await
+    }
+
+    async fn refresh(&self, stale_access_token: &str) -> Result<String, TokenError> {
+        token::refresh_session(&self.app_state, &self.session_id, stale_access_token).await
+    }
+}
+
+#[derive(Serialize)]
+pub struct NewPlaylist<'a> {
+    pub name: &'a str,
+    pub public: bool,
+    pub description: &'a str,
+}
+
+#[derive(Deserialize)]
+struct SnapshotResponse {
+    snapshot_id: String,
+}
+
+/// Shared by every request so connections to Spotify are pooled
+#[derive(Clone)]
+pub struct SpotifyClient {
+    http: reqwest::Client,
+    config: Arc<SpotifyConfig>,
+    retry: RetryPolicy,
+    /// Set by a 429 so concurrent requests hold off too instead of each running into it
+    rate_limited_until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl SpotifyClient {
+    pub fn new(config: SpotifyConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            retry: config.retry.clone(),
+            config: Arc::new(config),
+            rate_limited_until: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    pub fn config(&self) -> &SpotifyConfig {
+        &self.config
+    }
+
+    pub async fn me(&self, caller: &Caller) -> Result<SpotifyUserProfile, SpotifyError> {
+        let url = self.config.api_url("me");
+        json(self.send(caller, |http, token| http.get(url.clone()).bearer_auth(token)).await?).await
+    }
+
+    /// The profile behind a token that isn't attached to a session yet, used while logging in
+    pub async fn me_with_token(&self, access_token: &str) -> Result<SpotifyUserProfile, SpotifyError> {
+        let request = self.http.get(self.config.api_url("me")).bearer_auth(access_token);
+        json(check_status(self.execute(request).await?).await?).await
+    }
+
+    pub async fn playlists_page(&self, caller: &Caller, limit: u32, offset: u32) -> Result<SpotifyPlaylistsResponse, SpotifyError> {
+        let url = self.playlists_url(limit, offset);
+        json(self.send(caller, |http, token| http.get(url.clone()).bearer_auth(token)).await?).await
+    }
+
+    /// All of the caller's playlists, owned and followed
+    pub fn playlists<'a>(&'a self, caller: &'a Caller) -> impl Stream<Item = Result<SpotifyPlaylistItem, SpotifyError>> + 'a {
+        self.items(caller, self.playlists_url(PLAYLISTS_PAGE_LIMIT, 0), None)
+    }
+
+    pub async fn playlist(&self, caller: &Caller, playlist_id: &str) -> Result<SpotifyPlaylistItem, SpotifyError> {
+        let url = self.config.api_url(&format!("playlists/{}", playlist_id));
+        json(self.send(caller, |http, token| http.get(url.clone()).bearer_auth(token)).await?).await
+    }
+
+    pub async fn playlist_tracks_page(
+        &self,
+        caller: &Caller,
+        playlist_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<SpotifyPlaylistTrackResponse, SpotifyError> {
+        let url = self.playlist_tracks_url(playlist_id, limit, offset);
+        json(self.send(caller, |http, token| http.get(url.clone()).bearer_auth(token)).await?).await
+    }
+
+    /// How many items the playlist holds right now
+    pub async fn playlist_track_count(&self, caller: &Caller, playlist_id: &str) -> Result<u32, SpotifyError> {
+        Ok(self.playlist_tracks_page(caller, playlist_id, 1, 0).await?.total)
+    }
+
+    /// Every item of a playlist in playlist order, fetching pages concurrently
+    pub async fn playlist_tracks_all(&self, caller: &Caller, playlist_id: &str) -> Result<Vec<PlaylistItemTrackWrapper>, SpotifyError> {
+        self.collect_concurrently(
+            caller,
+            |limit, offset| self.playlist_tracks_url(playlist_id, limit, offset),
+            PLAYLIST_TRACKS_PAGE_LIMIT,
+            PAGE_FETCH_CONCURRENCY,
+        )
+        .await
+    }
+
+    /// A page of the caller's Liked Songs, newest first. Items have no `added_by`.
+    pub async fn saved_tracks_page(&self, caller: &Caller, limit: u32, offset: u32) -> Result<SpotifyPlaylistTrackResponse, SpotifyError> {
+        let url = self.saved_tracks_url(limit, offset);
+        json(self.send(caller, |http, token| http.get(url.clone()).bearer_auth(token)).await?).await
+    }
+
+    pub async fn saved_track_count(&self, caller: &Caller) -> Result<u32, SpotifyError> {
+        Ok(self.saved_tracks_page(caller, 1, 0).await?.total)
+    }
+
+    /// All of the caller's Liked Songs, fetching pages concurrently
+    pub async fn saved_tracks_all(&self, caller: &Caller) -> Result<Vec<PlaylistItemTrackWrapper>, SpotifyError> {
+        self.collect_concurrently(caller, |limit, offset| self.saved_tracks_url(limit, offset), SAVED_TRACKS_PAGE_LIMIT, PAGE_FETCH_CONCURRENCY)
+            .await
+    }
+
+    fn playlists_url(&self, limit: u32, offset: u32) -> reqwest::Url {
+        let mut url = self.config.api_url("me/playlists");
+        url.query_pairs_mut()
+            .append_pair("limit", &limit.to_string())
+            .append_pair("offset", &offset.to_string());
+        url
+    }
+
+    fn saved_tracks_url(&self, limit: u32, offset: u32) -> reqwest::Url {
+        let mut url = self.config.api_url("me/tracks");
+        url.query_pairs_mut()
+            .append_pair("offset", &offset.to_string())
+            .append_pair("limit", &limit.to_string())
+            .append_pair("market", "from_token");
+        url
+    }
+
+    fn playlist_tracks_url(&self, playlist_id: &str, limit: u32, offset: u32) -> reqwest::Url {
+        let mut url = self.config.api_url(&format!("playlists/{}/tracks", playlist_id));
+        url.query_pairs_mut()
+            .append_pair("offset", &offset.to_string())
+            .append_pair("limit", &limit.to_string())
+            .append_pair("market", "from_token")
+            .append_pair("additional_types", "track,episode")
+            .append_pair("fields", PLAYLIST_TRACK_FIELDS);
+        url
+    }
+
+    pub async fn create_playlist(
+        &self,
+        caller: &Caller,
+        user_id: &str,
+        playlist: &NewPlaylist<'_>,
+    ) -> Result<SpotifyPlaylistItem, SpotifyError> {
+        let url = self.config.api_url(&format!("users/{}/playlists", user_id));
+        json(self.send(caller, |http, token| http.post(url.clone()).bearer_auth(token).json(playlist)).await?).await
+    }
+
+    /// Appends up to 100 items and returns the playlist's new snapshot id
+    pub async fn add_tracks(&self, caller: &Caller, playlist_id: &str, uris: &[String]) -> Result<String, SpotifyError> {
+        #[derive(Serialize)]
+        struct AddTracks<'a> {
+            uris: &'a [String],
+        }
+
+        let url = self.config.api_url(&format!("playlists/{}/tracks", playlist_id));
+        let body = AddTracks { uris };
+        let response = self.send(caller, |http, token| http.post(url.clone()).bearer_auth(token).json(&body)).await?;
+        Ok(json::<SnapshotResponse>(response).await?.snapshot_id)
+    }
+
+    /// Replaces every item with up to 100 `uris` and returns the playlist's new snapshot id
+    pub async fn replace_tracks(&self, caller: &Caller, playlist_id: &str, uris: &[String]) -> Result<String, SpotifyError> {
+        #[derive(Serialize)]
+        struct ReplaceTracks<'a> {
+            uris: &'a [String],
+        }
+
+        let url = self.config.api_url(&format!("playlists/{}/tracks", playlist_id));
+        let body = ReplaceTracks { uris };
+        let response = self.send(caller, |http, token| http.put(url.clone()).bearer_auth(token).json(&body)).await?;
+        Ok(json::<SnapshotResponse>(response).await?.snapshot_id)
+    }
+
+    /// Moves `range_length` items starting at `range_start` to before `insert_before`, positions as
+    /// of `snapshot_id`. Spotify doesn't reject an older snapshot, it applies the move there and
+    /// merges later edits, so callers check the result themselves. Returns the new snapshot id.
+    pub async fn reorder_tracks(
+        &self,
+        caller: &Caller,
+        playlist_id: &str,
+        range_start: usize,
+        range_length: usize,
+        insert_before: usize,
+        snapshot_id: &str,
+    ) -> Result<String, SpotifyError> {
+        #[derive(Serialize)]
+        struct ReorderTracks<'a> {
+            range_start: usize,
+            range_length: usize,
+            insert_before: usize,
+            snapshot_id: &'a str,
+        }
+
+        let url = self.config.api_url(&format!("playlists/{}/tracks", playlist_id));
+        let body = ReorderTracks { range_start, range_length, insert_before, snapshot_id };
+        let response = self.send(caller, |http, token| http.put(url.clone()).bearer_auth(token).json(&body)).await?;
+        Ok(json::<SnapshotResponse>(response).await?.snapshot_id)
+    }
+
+    pub async fn set_description(&self, caller: &Caller, playlist_id: &str, description: &str) -> Result<(), SpotifyError> {
+        #[derive(Serialize)]
+        struct ChangeDetails<'a> {
+            description: &'a str,
+        }
+
+        let url = self.config.api_url(&format!("playlists/{}", playlist_id));
+        let body = ChangeDetails { description };
+        self.send(caller, |http, token| http.put(url.clone()).bearer_auth(token).json(&body)).await?;
+        Ok(())
+    }
+
+    /// `jpeg_base64` is the base64 encoded JPEG Spotify expects as the body
+    pub async fn upload_cover(&self, caller: &Caller, playlist_id: &str, jpeg_base64: &str) -> Result<(), SpotifyError> {
+        let url = self.config.api_url(&format!("playlists/{}/images", playlist_id));
+        self.send(caller, |http, token| {
+            http.put(url.clone())
+                .bearer_auth(token)
+                .header(CONTENT_TYPE, "image/jpeg")
+                .body(jpeg_base64.to_string())
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Downloads an image from Spotify's CDN, which needs no token
+    pub async fn fetch_image(&self, url: &str) -> Result<Vec<u8>, SpotifyError> {
+        let response = check_status(self.execute(self.http.get(url)).await?).await?;
+        Ok(response.bytes().await.map_err(SpotifyError::Network)?.to_vec())
+    }
+
+    /// Sends the request built with the caller's access token. If Spotify answers 401 the
+    /// token is refreshed once and the request is rebuilt and sent again.
+    async fn send(
+        &self,
+        caller: &Caller,
+        build_request: impl Fn(&reqwest::Client, &str) -> RequestBuilder,
+    ) -> Result<Response, SpotifyError> {
+        let access_token = caller.access_token().await?;
+        let response = self.execute(build_request(&self.http, &access_token)).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return check_status(response).await;
+        }
+
+        tracing::warn!("Spotify rejected access token, refreshing and retrying");
+        let access_token = caller.refresh(&access_token).await?;
+        check_status(self.execute(build_request(&self.http, &access_token)).await?).await
+    }
+
+    /// Sends the request, retrying rate limits and transient failures as the [`RetryPolicy`] allows
+    async fn execute(&self, request: RequestBuilder) -> Result<Response, SpotifyError> {
+        let request = request.build().map_err(SpotifyError::Network)?;
+        let (method, path) = (request.method().clone(), request.url().path().to_string());
+        let started = Instant::now();
+        let mut attempt = 0;
+
+        loop {
+            self.wait_out_rate_limit().await;
+            attempt += 1;
+            tracing::debug!("Spotify {} {} (attempt {})", method, path, attempt);
+            // every body we send is in memory, so this only fails for streams
+            let this_attempt = request.try_clone().expect("spotify request bodies can be cloned");
+
+            let retry = match self.http.execute(this_attempt).await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => match self.retry.after_status(&method, response.status(), response.headers(), attempt, started) {
+                    Retry::After(delay) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                        tracing::warn!("Spotify {} {} was rate limited, retrying in {:?}", method, path, delay);
+                        self.hold_off(delay);
+                        continue;
+                    }
+                    Retry::After(delay) => {
+                        tracing::warn!("Spotify {} {} answered {}, retrying in {:?}", method, path, response.status(), delay);
+                        delay
+                    }
+                    Retry::GiveUp => return Ok(response),
+                },
+                Err(e) => match self.retry.after_error(&method, &e, attempt, started) {
+                    Retry::After(delay) => {
+                        tracing::warn!("Network error on Spotify {} {}: {}, retrying in {:?}", method, path, e, delay);
+                        delay
+                    }
+                    Retry::GiveUp => {
+                        tracing::error!("Network error on Spotify {} {}: {}", method, path, e);
+                        return Err(SpotifyError::Network(e));
+                    }
+                },
+            };
+            tokio::time::sleep(retry).await;
+        }
+    }
+
+    fn hold_off(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        let mut limited = self.rate_limited_until.lock().unwrap();
+        if limited.is_none_or(|current| current < until) {
+            *limited = Some(until);
+        }
+    }
+
+    async fn wait_out_rate_limit(&self) {
+        let until = *self.rate_limited_until.lock().unwrap();
+        if let Some(wait) = until.and_then(|until| until.checked_duration_since(Instant::now())) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+async fn check_status(response: Response) -> Result<Response, SpotifyError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let path = response.url().path().to_string();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = retry::retry_after(response.headers());
+        tracing::error!("Spotify request to {} was still rate limited after retrying", path);
+        return Err(SpotifyError::RateLimited { retry_after });
+    }
+    let body = response.text().await.unwrap_or_else(|_| "Unknown Error".to_string());
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|value| value["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or(body);
+    tracing::error!("Spotify request to {} failed with {}: {}", path, status, message);
+    Err(SpotifyError::Api { status, message })
+}
+
+async fn json<T: DeserializeOwned>(response: Response) -> Result<T, SpotifyError> {
+    response.json::<T>().await.map_err(|e| {
+        tracing::error!("Failed to parse Spotify response: {}", e);
+        SpotifyError::Parse(e)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::Method;
+    use futures::{StreamExt, TryStreamExt};
+
+    use super::*;
+    use crate::test_support::{log_in, test_app_state, test_config, FakeSpotify, FakeTrack};
+
+    async fn setup_with(retry: impl FnOnce(&mut RetryPolicy)) -> (FakeSpotify, SpotifyClient, Caller) {
+        let fake = FakeSpotify::start().await;
+        fake.add_user("alice", "Alice");
+        let mut config = test_config(&fake, "http://127.0.0.1/callback");
+        retry(&mut config.spotify.retry);
+        let app_state = test_app_state(config);
+        let cookie = log_in(&app_state, &fake, "alice");
+
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(axum::http::header::COOKIE, cookie.parse().unwrap());
+        let session_id = app_state.sessions.session_id_from_headers(&headers).unwrap();
+        (fake, app_state.spotify.clone(), Caller::new(app_state, session_id))
+    }
+
+    async fn setup() -> (FakeSpotify, SpotifyClient, Caller) {
+        setup_with(|_| {}).await
+    }
+
+    #[tokio::test]
+    async fn waits_out_retry_after_on_429() {
+        let (fake, client, caller) = setup().await;
+        fake.rate_limit(Method::GET, "/v1/me", 1, 1);
+
+        let started = Instant::now();
+        let profile = client.me(&caller).await.unwrap();
+
+        assert_eq!(profile.id, "alice");
+        assert!(started.elapsed() >= Duration::from_secs(1), "retried before Retry-After passed");
+        assert_eq!(fake.count("GET", "/v1/me"), 2);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_posts_are_retried() {
+        let (fake, client, caller) = setup().await;
+        let playlist_id = fake.add_playlist("alice", "Mine", Vec::new());
+        let path = format!("/v1/playlists/{}/tracks", playlist_id);
+        fake.rate_limit(Method::POST, &path, 0, 2);
+
+        client.add_tracks(&caller, &playlist_id, &["spotify:track:a".to_string()]).await.unwrap();
+
+        assert_eq!(fake.count("POST", &path), 3);
+        assert_eq!(fake.playlist(&playlist_id).unwrap().tracks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn server_errors_on_reads_are_retried_with_backoff() {
+        let (fake, client, caller) = setup().await;
+        fake.fail(Method::GET, "/v1/me/playlists", StatusCode::BAD_GATEWAY, 3);
+
+        let page = client.playlists_page(&caller, 50, 0).await.unwrap();
+
+        assert_eq!(page.total, 0);
+        assert_eq!(fake.count("GET", "/v1/me/playlists"), 4);
+    }
+
+    #[tokio::test]
+    async fn server_errors_on_posts_are_not_retried() {
+        let (fake, client, caller) = setup().await;
+        fake.fail(Method::POST, "/v1/users/alice/playlists", StatusCode::BAD_GATEWAY, 1);
+
+        let result = client
+            .create_playlist(&caller, "alice", &NewPlaylist { name: "New", public: false, description: "" })
+            .await;
+
+        assert!(matches!(result, Err(SpotifyError::Api { status: StatusCode::BAD_GATEWAY, .. })));
+        assert_eq!(fake.count("POST", "/v1/users/alice/playlists"), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_retry_after_is_past_the_deadline() {
+        let (fake, client, caller) = setup().await;
+        fake.rate_limit(Method::GET, "/v1/me", 600, 1);
+
+        let result = client.me(&caller).await;
+
+        assert!(matches!(result, Err(SpotifyError::RateLimited { retry_after: Some(wait) }) if wait == Duration::from_secs(600)));
+        assert_eq!(fake.count("GET", "/v1/me"), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (fake, client, caller) = setup_with(|retry| retry.max_attempts = 3).await;
+        fake.fail(Method::GET, "/v1/me", StatusCode::SERVICE_UNAVAILABLE, 10);
+
+        assert!(client.me(&caller).await.is_err());
+        assert_eq!(fake.count("GET", "/v1/me"), 3);
+    }
+
+    #[tokio::test]
+    async fn items_stream_every_page_in_order() {
+        let (fake, client, caller) = setup().await;
+        let tracks = FakeTrack::many("t", 230, 4);
+        let playlist_id = fake.add_playlist("alice", "Long", tracks.clone());
+
+        let first = client.playlist_tracks_url(&playlist_id, PLAYLIST_TRACKS_PAGE_LIMIT, 0);
+        let streamed: Vec<PlaylistItemTrackWrapper> = client.items(&caller, first, None).try_collect().await.unwrap();
+
+        let uris: Vec<String> = streamed.into_iter().map(|item| item.track.unwrap().uri).collect();
+        let expected: Vec<String> = tracks.into_iter().map(|track| track.uri).collect();
+        assert_eq!(uris, expected);
+        assert_eq!(fake.count("GET", &format!("/v1/playlists/{}/tracks", playlist_id)), 3);
+    }
+
+    #[tokio::test]
+    async fn playlist_items_carry_full_track_metadata() {
+        let (fake, client, caller) = setup().await;
+        let playlist_id = fake.add_playlist("alice", "Meta", vec![FakeTrack::track("t1", "a")]);
+
+        let item = client.playlist_tracks_all(&caller, &playlist_id).await.unwrap().remove(0);
+
+        assert_eq!(item.added_at.as_deref(), Some("2024-01-01T00:00:00Z"));
+        assert_eq!(item.added_by.unwrap().id, "alice");
+        let track = item.track.unwrap();
+        assert_eq!(track.artists[0].id.as_deref(), Some("artist-a"));
+        assert_eq!((track.duration_ms, track.popularity, track.is_playable), (180_000, Some(50), Some(true)));
+        assert_eq!(track.album.unwrap().release_year(), Some(2020));
+        let url = client.playlist_tracks_url(&playlist_id, 100, 0);
+        assert!(url.query_pairs().any(|(key, value)| key == "fields" && value.contains("artists(id,name)")));
+    }
+
+    #[tokio::test]
+    async fn concurrent_fetch_keeps_playlist_order() {
+        let (fake, client, caller) = setup().await;
+        let tracks = FakeTrack::many("t", 1050, 7);
+        let playlist_id = fake.add_playlist("alice", "Huge", tracks.clone());
+        fake.rate_limit(Method::GET, &format!("/v1/playlists/{}/tracks", playlist_id), 0, 3);
+
+        let fetched = client.playlist_tracks_all(&caller, &playlist_id).await.unwrap();
+
+        let uris: Vec<String> = fetched.into_iter().map(|item| item.track.unwrap().uri).collect();
+        let expected: Vec<String> = tracks.into_iter().map(|track| track.uri).collect();
+        assert_eq!(uris, expected);
+        // 11 pages of 100, plus the three rate limited attempts
+        assert_eq!(fake.count("GET", &format!("/v1/playlists/{}/tracks", playlist_id)), 14);
+    }
+
+    #[tokio::test]
+    async fn concurrent_fetch_fails_if_any_page_fails() {
+        let (fake, client, caller) = setup_with(|retry| retry.max_attempts = 1).await;
+        let playlist_id = fake.add_playlist("alice", "Huge", FakeTrack::many("t", 500, 7));
+        let path = format!("/v1/playlists/{}/tracks", playlist_id);
+        // let the first page through, then break one of the rest
+        client.playlist_tracks_page(&caller, &playlist_id, 1, 0).await.unwrap();
+        fake.fail(Method::GET, &path, StatusCode::INTERNAL_SERVER_ERROR, 1);
+
+        assert!(client.playlist_tracks_all(&caller, &playlist_id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn a_429_holds_off_other_requests() {
+        let (fake, client, caller) = setup().await;
+        fake.rate_limit(Method::GET, "/v1/me", 1, 1);
+
+        let started = Instant::now();
+        let (profile, page) = tokio::join!(client.me(&caller), async {
+            // give the rate limited request a head start
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            client.playlists_page(&caller, 50, 0).await
+        });
+
+        profile.unwrap();
+        page.unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(fake.count("GET", "/v1/me/playlists"), 1);
+    }
+
+    #[tokio::test]
+    async fn max_items_stops_before_fetching_further_pages() {
+        let (fake, client, caller) = setup().await;
+        for i in 0..120 {
+            fake.add_playlist("alice", &format!("Playlist {}", i), Vec::new());
+        }
+
+        let first_sixty: Vec<SpotifyPlaylistItem> =
+            client.items(&caller, client.playlists_url(50, 0), Some(60)).try_collect().await.unwrap();
+
+        assert_eq!(first_sixty.len(), 60);
+        assert_eq!(first_sixty[0].name, "Playlist 0");
+        assert_eq!(fake.count("GET", "/v1/me/playlists"), 2);
+    }
+
+    #[tokio::test]
+    async fn dropping_the_stream_stops_fetching() {
+        let (fake, client, caller) = setup().await;
+        for i in 0..120 {
+            fake.add_playlist("alice", &format!("Playlist {}", i), Vec::new());
+        }
+
+        let first = Box::pin(client.playlists(&caller)).next().await.unwrap().unwrap();
+
+        assert_eq!(first.name, "Playlist 0");
+        assert_eq!(fake.count("GET", "/v1/me/playlists"), 1);
+    }
+
+    #[tokio::test]
+    async fn page_errors_end_the_stream_with_the_error() {
+        let (fake, client, caller) = setup_with(|retry| retry.max_attempts = 1).await;
+        for i in 0..60 {
+            fake.add_playlist("alice", &format!("Playlist {}", i), Vec::new());
+        }
+        let mut playlists = Box::pin(client.playlists(&caller));
+        for _ in 0..50 {
+            playlists.next().await.unwrap().unwrap();
+        }
+        fake.fail(Method::GET, "/v1/me/playlists", StatusCode::INTERNAL_SERVER_ERROR, 1);
+
+        assert!(matches!(playlists.next().await, Some(Err(SpotifyError::Api { .. }))));
+        assert!(playlists.next().await.is_none());
+    }
+}
@@ -0,0 +1,81 @@
+//! Walks Spotify paging objects, either by following their `next` links or, when the total
+//! is known up front, by fetching the remaining offsets concurrently.
+//!
+//! Streams are lazy: a page is only requested once the items before it have been consumed,
+//! so dropping the stream (or `take`-ing from it) stops the fetching too.
+
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+
+use super::{json, Caller, SpotifyClient, SpotifyError};
+use crate::api_models::Paging;
+
+impl SpotifyClient {
+    /// Every page starting at `first`, in order
+    pub fn pages<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        caller: &'a Caller,
+        first: Url,
+    ) -> impl Stream<Item = Result<Paging<T>, SpotifyError>> + 'a {
+        stream::try_unfold(Some(first), move |next| async move {
+            let Some(url) = next else {
+                return Ok(None);
+            };
+            let page: Paging<T> = self.page(caller, url).await?;
+
+            // an empty page with a next link would have us loop forever
+            let next = match page.next.as_deref() {
+                Some(_) if page.items.is_empty() => None,
+                Some(next) => Some(Url::parse(next).map_err(|e| SpotifyError::Pagination(format!("bad next url {}: {}", next, e)))?),
+                None => None,
+            };
+            tracing::debug!("Fetched page of {} items at offset {}, total {}", page.items.len(), page.offset, page.total);
+            Ok(Some((page, next)))
+        })
+    }
+
+    /// The items of every page starting at `first`, stopping after `max_items` if given
+    pub fn items<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        caller: &'a Caller,
+        first: Url,
+        max_items: Option<usize>,
+    ) -> impl Stream<Item = Result<T, SpotifyError>> + 'a {
+        self.pages(caller, first)
+            .map_ok(|page| stream::iter(page.items.into_iter().map(Ok)))
+            .try_flatten()
+            .take(max_items.unwrap_or(usize::MAX))
+    }
+
+    /// Every item of an offset-paged endpoint. The first page's `total` tells us all the other
+    /// offsets, so those pages are fetched `concurrency` at a time and put back in order.
+    /// `page_url` builds the url for a `(limit, offset)`.
+    pub async fn collect_concurrently<T: DeserializeOwned>(
+        &self,
+        caller: &Caller,
+        page_url: impl Fn(u32, u32) -> Url,
+        limit: u32,
+        concurrency: usize,
+    ) -> Result<Vec<T>, SpotifyError> {
+        let first: Paging<T> = self.page(caller, page_url(limit, 0)).await?;
+        let total = first.total;
+        let mut items = Vec::with_capacity(total as usize);
+        items.extend(first.items);
+
+        let rest: Vec<Paging<T>> = stream::iter((limit..total).step_by(limit as usize))
+            .map(|offset| self.page(caller, page_url(limit, offset)))
+            .buffered(concurrency)
+            .try_collect()
+            .await?;
+        for page in rest {
+            items.extend(page.items);
+        }
+        tracing::debug!("Fetched {} of {} items concurrently", items.len(), total);
+        Ok(items)
+    }
+
+    async fn page<T: DeserializeOwned>(&self, caller: &Caller, url: Url) -> Result<Paging<T>, SpotifyError> {
+        json(self.send(caller, |http, token| http.get(url.clone()).bearer_auth(token)).await?).await
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+use rand::{thread_rng, Rng};
+use reqwest::{header::{HeaderMap, RETRY_AFTER}, Method, StatusCode};
+
+/// How hard [`super::SpotifyClient`] tries before giving up on a request.
+///
+/// 429s are always retried after the `Retry-After` Spotify sends. 5xx answers and network
+/// errors get jittered exponential backoff, but only for requests that are safe to send twice:
+/// a POST that timed out may still have added its tracks.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// No retry is started that would finish waiting after this much time since the first attempt
+    pub deadline: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 6,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            deadline: Duration::from_secs(120),
+        }
+    }
+}
+
+/// What to do after an attempt that didn't succeed
+#[derive(Debug, PartialEq)]
+pub enum Retry {
+    After(Duration),
+    GiveUp,
+}
+
+impl RetryPolicy {
+    /// `attempt` counts from 1, `started` is when the first attempt was sent
+    pub fn after_status(&self, method: &Method, status: StatusCode, headers: &HeaderMap, attempt: u32, started: Instant) -> Retry {
+        let delay = if status == StatusCode::TOO_MANY_REQUESTS {
+            retry_after(headers).unwrap_or_else(|| self.backoff(attempt))
+        } else if status.is_server_error() && is_idempotent(method) {
+            self.backoff(attempt)
+        } else {
+            return Retry::GiveUp;
+        };
+        self.within_budget(delay, attempt, started)
+    }
+
+    /// A request that never reached Spotify can always be sent again, anything else only if idempotent
+    pub fn after_error(&self, method: &Method, error: &reqwest::Error, attempt: u32, started: Instant) -> Retry {
+        let retryable = error.is_connect() || (is_idempotent(method) && (error.is_timeout() || error.is_request()));
+        if !retryable {
+            return Retry::GiveUp;
+        }
+        self.within_budget(self.backoff(attempt), attempt, started)
+    }
+
+    fn within_budget(&self, delay: Duration, attempt: u32, started: Instant) -> Retry {
+        if attempt >= self.max_attempts || started.elapsed() + delay > self.deadline {
+            return Retry::GiveUp;
+        }
+        Retry::After(delay)
+    }
+
+    /// Exponential backoff with "equal jitter": half the delay is fixed, the other half random
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let capped = exponential.min(self.max_delay);
+        let half = capped / 2;
+        half + thread_rng().gen_range(Duration::ZERO..=half)
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::PUT | Method::DELETE)
+}
+
+/// Spotify sends whole seconds
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
@@ -0,0 +1,21 @@
+-- a session can have several Spotify accounts linked, each with its own grant
+CREATE TABLE session_accounts (
+    session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    nonce BLOB NOT NULL,
+    ciphertext BLOB NOT NULL,
+    scope TEXT NOT NULL,
+    linked_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (session_id, user_id)
+);
+
+INSERT INTO session_accounts (session_id, user_id, nonce, ciphertext, scope, linked_at, updated_at)
+SELECT s.id, s.user_id, t.nonce, t.ciphertext, t.scope, s.created_at, t.updated_at
+FROM sessions s
+JOIN refresh_tokens t ON t.user_id = s.user_id;
+
+DROP TABLE refresh_tokens;
+
+DROP INDEX sessions_user_id;
+ALTER TABLE sessions RENAME COLUMN user_id TO active_user_id;
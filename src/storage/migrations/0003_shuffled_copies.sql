@@ -0,0 +1,8 @@
+-- the playlist each user's shuffles of a source playlist are written to, so a re-shuffle reuses it
+CREATE TABLE shuffled_copies (
+    spotify_user_id TEXT NOT NULL REFERENCES users(spotify_id) ON DELETE CASCADE,
+    source_playlist_id TEXT NOT NULL,
+    copy_playlist_id TEXT NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (spotify_user_id, source_playlist_id)
+);
@@ -0,0 +1,8 @@
+-- the order a playlist had before it was shuffled in place, kept until it is undone or shuffled again
+CREATE TABLE shuffle_undo (
+    spotify_user_id TEXT NOT NULL REFERENCES users(spotify_id) ON DELETE CASCADE,
+    playlist_id TEXT NOT NULL,
+    uris TEXT NOT NULL, -- JSON array of the playlist's item URIs in their old order
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (spotify_user_id, playlist_id)
+);
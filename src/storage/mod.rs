@@ -0,0 +1,307 @@
+use std::{sync::{Arc, Mutex}, time::{SystemTime, UNIX_EPOCH}};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// Applied in order, `PRAGMA user_version` records how many have run
+const MIGRATIONS: &[&str] = &[
+    include_str!("migrations/0001_init.sql"),
+    include_str!("migrations/0002_session_accounts.sql"),
+    include_str!("migrations/0003_shuffled_copies.sql"),
+    include_str!("migrations/0004_shuffle_undo.sql"),
+];
+
+pub const IN_MEMORY: &str = ":memory:";
+
+/// SQLite backed store for users, login sessions, the (encrypted) refresh
+/// token of every account linked to a session, where shuffles were saved and
+/// what in-place shuffles can be undone to.
+#[derive(Clone)]
+pub struct Storage {
+    conn: Arc<Mutex<Connection>>,
+    cipher: Arc<XChaCha20Poly1305>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredUser {
+    pub id: i64,
+    pub spotify_id: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredAccount {
+    pub user: StoredUser,
+    pub refresh_token: String,
+    pub scope: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredSession {
+    pub session_id: String,
+    pub active_user_id: i64,
+    pub accounts: Vec<StoredAccount>,
+}
+
+impl Storage {
+    /// Opens (or creates) the database at `path`, `:memory:` gives a throwaway one
+    pub fn open(path: &str, encryption_key: [u8; 32]) -> Result<Self> {
+        let mut conn = if path == IN_MEMORY {
+            Connection::open_in_memory()
+        } else {
+            Connection::open(path)
+        }
+        .with_context(|| format!("failed to open database at {}", path))?;
+
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        migrate(&mut conn)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            cipher: Arc::new(XChaCha20Poly1305::new(&encryption_key.into())),
+        })
+    }
+
+    /// Decodes a base64 encoded 32 byte key, as found in TOKEN_ENCRYPTION_KEY
+    pub fn parse_key(encoded: &str) -> Result<[u8; 32]> {
+        let bytes = STANDARD.decode(encoded.trim()).context("encryption key is not valid base64")?;
+        bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| anyhow!("encryption key must be 32 bytes, got {}", bytes.len()))
+    }
+
+    pub fn random_key() -> [u8; 32] {
+        XChaCha20Poly1305::generate_key(&mut OsRng).into()
+    }
+
+    pub fn upsert_user(&self, spotify_id: &str, display_name: &str) -> Result<StoredUser> {
+        let conn = self.conn.lock().unwrap();
+        let now = unix_now();
+        let id = conn.query_row(
+            "INSERT INTO users (spotify_id, display_name, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?3)
+             ON CONFLICT(spotify_id) DO UPDATE SET display_name = excluded.display_name, updated_at = excluded.updated_at
+             RETURNING id",
+            params![spotify_id, display_name, now],
+            |row| row.get(0),
+        )?;
+
+        Ok(StoredUser {
+            id,
+            spotify_id: spotify_id.to_string(),
+            display_name: display_name.to_string(),
+        })
+    }
+
+    /// Links an account to the session (creating the session if needed) and makes it the active one
+    pub fn link_account(&self, session_id: &str, user_id: i64, refresh_token: &str, scope: &str) -> Result<()> {
+        let (nonce, ciphertext) = self.encrypt(user_id, refresh_token)?;
+        let now = unix_now();
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO sessions (id, active_user_id, created_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(id) DO UPDATE SET active_user_id = excluded.active_user_id",
+            params![session_id, user_id, now],
+        )?;
+        tx.execute(
+            "INSERT INTO session_accounts (session_id, user_id, nonce, ciphertext, scope, linked_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+             ON CONFLICT(session_id, user_id) DO UPDATE SET nonce = excluded.nonce, ciphertext = excluded.ciphertext,
+                scope = excluded.scope, updated_at = excluded.updated_at",
+            params![session_id, user_id, nonce, ciphertext, scope, now],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn save_refresh_token(&self, session_id: &str, user_id: i64, refresh_token: &str, scope: &str) -> Result<()> {
+        let (nonce, ciphertext) = self.encrypt(user_id, refresh_token)?;
+
+        self.conn.lock().unwrap().execute(
+            "UPDATE session_accounts SET nonce = ?3, ciphertext = ?4, scope = ?5, updated_at = ?6
+             WHERE session_id = ?1 AND user_id = ?2",
+            params![session_id, user_id, nonce, ciphertext, scope, unix_now()],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_active_account(&self, session_id: &str, user_id: i64) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE sessions SET active_user_id = ?2 WHERE id = ?1",
+            params![session_id, user_id],
+        )?;
+        Ok(())
+    }
+
+    /// Forgets one account's tokens without touching the rest of the session
+    pub fn unlink_account(&self, session_id: &str, user_id: i64) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "DELETE FROM session_accounts WHERE session_id = ?1 AND user_id = ?2",
+            params![session_id, user_id],
+        )?;
+        Ok(())
+    }
+
+    /// Drops the session along with the tokens of every account linked to it
+    pub fn delete_session(&self, session_id: &str) -> Result<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM sessions WHERE id = ?1", params![session_id])?;
+        Ok(())
+    }
+
+    /// The playlist `spotify_user_id`'s last shuffle of `source_playlist_id` went to
+    pub fn shuffled_copy(&self, spotify_user_id: &str, source_playlist_id: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let copy = conn
+            .query_row(
+                "SELECT copy_playlist_id FROM shuffled_copies WHERE spotify_user_id = ?1 AND source_playlist_id = ?2",
+                params![spotify_user_id, source_playlist_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(copy)
+    }
+
+    pub fn remember_shuffled_copy(&self, spotify_user_id: &str, source_playlist_id: &str, copy_playlist_id: &str) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO shuffled_copies (spotify_user_id, source_playlist_id, copy_playlist_id, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(spotify_user_id, source_playlist_id) DO UPDATE SET copy_playlist_id = excluded.copy_playlist_id,
+                updated_at = excluded.updated_at",
+            params![spotify_user_id, source_playlist_id, copy_playlist_id, unix_now()],
+        )?;
+        Ok(())
+    }
+
+    /// Keeps `uris`, a playlist's order before an in-place shuffle, replacing any older one
+    pub fn save_undo_order(&self, spotify_user_id: &str, playlist_id: &str, uris: &[String]) -> Result<()> {
+        let uris = serde_json::to_string(uris)?;
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO shuffle_undo (spotify_user_id, playlist_id, uris, created_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(spotify_user_id, playlist_id) DO UPDATE SET uris = excluded.uris, created_at = excluded.created_at",
+            params![spotify_user_id, playlist_id, uris, unix_now()],
+        )?;
+        Ok(())
+    }
+
+    pub fn undo_order(&self, spotify_user_id: &str, playlist_id: &str) -> Result<Option<Vec<String>>> {
+        let conn = self.conn.lock().unwrap();
+        let uris: Option<String> = conn
+            .query_row(
+                "SELECT uris FROM shuffle_undo WHERE spotify_user_id = ?1 AND playlist_id = ?2",
+                params![spotify_user_id, playlist_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        uris.map(|uris| serde_json::from_str(&uris).context("stored undo order is not a list of URIs"))
+            .transpose()
+    }
+
+    pub fn forget_undo_order(&self, spotify_user_id: &str, playlist_id: &str) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "DELETE FROM shuffle_undo WHERE spotify_user_id = ?1 AND playlist_id = ?2",
+            params![spotify_user_id, playlist_id],
+        )?;
+        Ok(())
+    }
+
+    /// Every session with at least one account whose refresh token is still readable.
+    /// Accounts we can't decrypt (e.g. the key changed) are skipped with a warning.
+    pub fn load_sessions(&self) -> Result<Vec<StoredSession>> {
+        // (session id, active user id, user, nonce, ciphertext, scope)
+        type AccountRow = (String, i64, StoredUser, Vec<u8>, Vec<u8>, String);
+        let rows: Vec<AccountRow> = {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare(
+                "SELECT s.id, s.active_user_id, u.id, u.spotify_id, u.display_name, a.nonce, a.ciphertext, a.scope
+                 FROM sessions s
+                 JOIN session_accounts a ON a.session_id = s.id
+                 JOIN users u ON u.id = a.user_id
+                 ORDER BY s.id, a.linked_at",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    StoredUser { id: row.get(2)?, spotify_id: row.get(3)?, display_name: row.get(4)? },
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        let mut sessions: Vec<StoredSession> = Vec::new();
+        for (session_id, active_user_id, user, nonce, ciphertext, scope) in rows {
+            let refresh_token = match self.decrypt(user.id, &nonce, &ciphertext) {
+                Ok(refresh_token) => refresh_token,
+                Err(e) => {
+                    tracing::warn!("Skipping stored account {}: {}", user.spotify_id, e);
+                    continue;
+                }
+            };
+            let account = StoredAccount { user, refresh_token, scope };
+
+            match sessions.last_mut() {
+                Some(session) if session.session_id == session_id => session.accounts.push(account),
+                _ => sessions.push(StoredSession { session_id, active_user_id, accounts: vec![account] }),
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// Refresh tokens are sealed with the user id as associated data
+    fn encrypt(&self, user_id: i64, refresh_token: &str) -> Result<(Vec<u8>, Vec<u8>)> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = user_id.to_be_bytes();
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, Payload { msg: refresh_token.as_bytes(), aad: &aad })
+            .map_err(|_| anyhow!("failed to encrypt refresh token"))?;
+        Ok((nonce.to_vec(), ciphertext))
+    }
+
+    fn decrypt(&self, user_id: i64, nonce: &[u8], ciphertext: &[u8]) -> Result<String> {
+        if nonce.len() != 24 {
+            return Err(anyhow!("stored nonce has the wrong length"));
+        }
+        let aad = user_id.to_be_bytes();
+        let plaintext = self
+            .cipher
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| anyhow!("failed to decrypt refresh token"))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let applied: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)
+            .with_context(|| format!("migration {} failed", index + 1))?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+        tracing::info!("Applied database migration {}", index + 1);
+    }
+    Ok(())
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
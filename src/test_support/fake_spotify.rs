@@ -0,0 +1,807 @@
+//! An in-process stand-in for the parts of the Spotify Web API and accounts service the app talks to.
+//!
+//! It keeps users, playlists and tokens in memory, paginates the way Spotify does and can be told to
+//! answer the next few matching requests with an error instead.
+
+// handlers bail out early with the error `Response` Spotify would send
+#![allow(clippy::result_large_err)]
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::auth::pkce;
+
+/// Spotify caps `/me/playlists` and `/me/tracks` pages at 50 and playlist item pages at 100
+const MAX_PLAYLISTS_LIMIT: usize = 50;
+const MAX_SAVED_TRACKS_LIMIT: usize = 50;
+const MAX_TRACKS_LIMIT: usize = 100;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FakeTrack {
+    pub id: Option<String>,
+    pub uri: String,
+    pub name: String,
+    pub artists: Vec<(String, String)>,
+    pub album: (String, String),
+    pub duration_ms: u32,
+    pub kind: &'static str,
+    pub is_local: bool,
+}
+
+impl FakeTrack {
+    pub fn track(id: &str, artist: &str) -> Self {
+        Self {
+            id: Some(id.to_string()),
+            uri: format!("spotify:track:{}", id),
+            name: format!("Song {}", id),
+            artists: vec![(format!("artist-{}", artist), format!("Artist {}", artist))],
+            album: (format!("album-{}", artist), format!("Album {}", artist)),
+            duration_ms: 180_000,
+            kind: "track",
+            is_local: false,
+        }
+    }
+
+    /// A podcast episode, Spotify lists the show as its album and artist
+    pub fn episode(id: &str, show: &str) -> Self {
+        Self {
+            id: Some(id.to_string()),
+            uri: format!("spotify:episode:{}", id),
+            name: format!("Episode {}", id),
+            artists: vec![(format!("show-{}", show), format!("Show {}", show))],
+            album: (format!("show-{}", show), format!("Show {}", show)),
+            duration_ms: 3_600_000,
+            kind: "episode",
+            is_local: false,
+        }
+    }
+
+    /// A local file: no id, and a uri made of its tags
+    pub fn local(name: &str, artist: &str) -> Self {
+        Self {
+            id: None,
+            uri: format!("spotify:local:{}:Album+{}:{}:180", artist, artist, name),
+            name: name.to_string(),
+            artists: vec![(String::new(), artist.to_string())],
+            album: (String::new(), format!("Album {}", artist)),
+            duration_ms: 180_000,
+            kind: "track",
+            is_local: true,
+        }
+    }
+
+    /// `n` tracks spread round-robin over `artists` artists
+    pub fn many(prefix: &str, n: usize, artists: usize) -> Vec<Self> {
+        (0..n)
+            .map(|i| Self::track(&format!("{}{}", prefix, i), &(i % artists.max(1)).to_string()))
+            .collect()
+    }
+
+    fn to_json(&self) -> Value {
+        let artists: Vec<Value> = self
+            .artists
+            .iter()
+            .map(|(id, name)| json!({ "id": if self.is_local { None } else { Some(id) }, "name": name }))
+            .collect();
+        json!({
+            "id": self.id,
+            "uri": self.uri,
+            "name": self.name,
+            "type": self.kind,
+            "is_local": self.is_local,
+            "duration_ms": self.duration_ms,
+            "explicit": false,
+            "popularity": if self.is_local { None } else { Some(50) },
+            "is_playable": true,
+            "artists": artists,
+            "album": {
+                "id": if self.is_local { None } else { Some(&self.album.0) },
+                "name": self.album.1,
+                "images": [],
+                "release_date": if self.is_local { None } else { Some("2020-05-01") },
+                "release_date_precision": if self.is_local { None } else { Some("day") },
+            },
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FakePlaylist {
+    pub id: String,
+    pub name: String,
+    pub owner: String,
+    pub description: String,
+    pub public: bool,
+    pub collaborative: bool,
+    pub tracks: Vec<FakeTrack>,
+    pub image_url: Option<String>,
+    /// Every base64 body PUT to `/playlists/{id}/images`
+    pub cover_uploads: Vec<String>,
+    pub snapshot: u64,
+}
+
+impl FakePlaylist {
+    fn snapshot_id(&self) -> String {
+        format!("{}-snap-{}", self.id, self.snapshot)
+    }
+
+    fn had_snapshot(&self, snapshot_id: &str) -> bool {
+        snapshot_id
+            .strip_prefix(&format!("{}-snap-", self.id))
+            .and_then(|n| n.parse::<u64>().ok())
+            .is_some_and(|n| n >= 1 && n <= self.snapshot)
+    }
+
+    fn to_json(&self, base_url: &str) -> Value {
+        let images: Vec<Value> = self
+            .image_url
+            .iter()
+            .map(|url| json!({ "url": url, "height": 640, "width": 640 }))
+            .collect();
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "description": self.description,
+            "public": self.public,
+            "collaborative": self.collaborative,
+            "uri": format!("spotify:playlist:{}", self.id),
+            "snapshot_id": self.snapshot_id(),
+            "images": images,
+            "owner": { "id": self.owner, "display_name": self.owner },
+            "external_urls": { "spotify": format!("https://open.spotify.com/playlist/{}", self.id) },
+            "tracks": {
+                "href": format!("{}/v1/playlists/{}/tracks", base_url, self.id),
+                "total": self.tracks.len(),
+            },
+        })
+    }
+}
+
+/// The next `times` requests whose method and path match get `status` instead of an answer.
+/// A `*` segment in `path` matches any one segment, e.g. `/v1/playlists/*/tracks`.
+#[derive(Clone, Debug)]
+pub struct Fault {
+    pub method: Option<Method>,
+    pub path: String,
+    pub status: StatusCode,
+    pub retry_after: Option<u64>,
+    pub times: usize,
+    /// Let the request take effect and only then answer `status`, like a gateway timing out on a slow backend
+    pub after_handling: bool,
+}
+
+impl Fault {
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        let pattern = self.path.split('/');
+        let mut segments = path.split('/');
+        self.method.as_ref().is_none_or(|m| m == method)
+            && pattern.map(Some).chain(std::iter::once(None)).all(|wanted| match (wanted, segments.next()) {
+                (None, None) => true,
+                (Some("*"), Some(_)) => true,
+                (Some(wanted), Some(segment)) => wanted == segment,
+                _ => false,
+            })
+    }
+}
+
+#[derive(Default)]
+pub struct FakeState {
+    pub users: HashMap<String, String>,
+    pub playlists: HashMap<String, FakePlaylist>,
+    /// Playlist ids in the order users follow them, per user
+    pub library: HashMap<String, Vec<String>>,
+    /// Liked Songs per user, newest first
+    pub liked: HashMap<String, Vec<FakeTrack>>,
+    pub access_tokens: HashMap<String, String>,
+    pub refresh_tokens: HashMap<String, String>,
+    /// code -> (user, code_challenge)
+    auth_codes: HashMap<String, (String, String)>,
+    /// Who `/authorize` signs in, as if they clicked "Agree"
+    pub login_as: Option<String>,
+    pub faults: VecDeque<Fault>,
+    /// `METHOD /path` of every request that reached the fake, faults included
+    pub requests: Vec<String>,
+    next_id: u64,
+}
+
+impl FakeState {
+    fn next_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", prefix, self.next_id)
+    }
+}
+
+#[derive(Clone)]
+pub struct FakeSpotify {
+    pub base_url: String,
+    pub state: Arc<Mutex<FakeState>>,
+}
+
+impl FakeSpotify {
+    /// Binds to a random local port and serves until the test's runtime shuts down
+    pub async fn start() -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("bind fake spotify");
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let fake = Self {
+            base_url,
+            state: Arc::new(Mutex::new(FakeState::default())),
+        };
+
+        let router = Router::new()
+            .route("/authorize", get(authorize))
+            .route("/api/token", post(token))
+            .route("/v1/me", get(me))
+            .route("/v1/me/playlists", get(my_playlists))
+            .route("/v1/me/tracks", get(my_tracks))
+            .route("/v1/users/:user_id/playlists", post(create_playlist))
+            .route("/v1/playlists/:id", get(playlist).put(change_details))
+            .route("/v1/playlists/:id/tracks", get(playlist_tracks).post(add_tracks).put(replace_or_reorder_tracks))
+            .route("/v1/playlists/:id/images", get(cover_images).put(upload_cover))
+            .route("/images/:name", get(image_file))
+            .layer(middleware::from_fn_with_state(fake.clone(), inject_faults))
+            .with_state(fake.clone());
+
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.expect("fake spotify server");
+        });
+        fake
+    }
+
+    pub fn api_base_url(&self) -> String {
+        format!("{}/v1", self.base_url)
+    }
+
+    pub fn add_user(&self, id: &str, display_name: &str) {
+        self.state.lock().unwrap().users.insert(id.to_string(), display_name.to_string());
+    }
+
+    pub fn login_as(&self, user: &str) {
+        self.state.lock().unwrap().login_as = Some(user.to_string());
+    }
+
+    /// A valid (access, refresh) token pair, as if `user` had logged in earlier
+    pub fn issue_tokens(&self, user: &str) -> (String, String) {
+        issue_tokens(&mut self.state.lock().unwrap(), user)
+    }
+
+    /// Makes every outstanding access token invalid, like an hour passing
+    pub fn expire_access_tokens(&self) {
+        self.state.lock().unwrap().access_tokens.clear();
+    }
+
+    /// Creates a playlist in `owner`'s library, with a cover image
+    pub fn add_playlist(&self, owner: &str, name: &str, tracks: Vec<FakeTrack>) -> String {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id("pl");
+        let playlist = FakePlaylist {
+            image_url: Some(format!("{}/images/{}.jpg", self.base_url, id)),
+            ..new_playlist(&id, owner, name, tracks)
+        };
+        state.playlists.insert(id.clone(), playlist);
+        state.library.entry(owner.to_string()).or_default().push(id.clone());
+        id
+    }
+
+    pub fn playlist(&self, id: &str) -> Option<FakePlaylist> {
+        self.state.lock().unwrap().playlists.get(id).cloned()
+    }
+
+    /// Adds `tracks` to the top of the user's Liked Songs
+    pub fn like(&self, user: &str, tracks: Vec<FakeTrack>) {
+        self.state.lock().unwrap().liked.entry(user.to_string()).or_default().splice(0..0, tracks);
+    }
+
+    /// Takes the playlist out of the user's library, which is all deleting one of your own does
+    pub fn unfollow(&self, user: &str, id: &str) {
+        if let Some(library) = self.state.lock().unwrap().library.get_mut(user) {
+            library.retain(|followed| followed != id);
+        }
+    }
+
+    pub fn playlists_named(&self, name: &str) -> Vec<FakePlaylist> {
+        let state = self.state.lock().unwrap();
+        state.playlists.values().filter(|p| p.name == name).cloned().collect()
+    }
+
+    pub fn inject(&self, fault: Fault) {
+        self.state.lock().unwrap().faults.push_back(fault);
+    }
+
+    /// Shorthand for `times` failures with `status` on `method path`
+    pub fn fail(&self, method: Method, path: &str, status: StatusCode, times: usize) {
+        self.inject(Fault { method: Some(method), path: path.to_string(), status, retry_after: None, times, after_handling: false });
+    }
+
+    /// Like [`FakeSpotify::fail`], but the requests take effect before failing
+    pub fn fail_after_handling(&self, method: Method, path: &str, status: StatusCode, times: usize) {
+        self.inject(Fault { method: Some(method), path: path.to_string(), status, retry_after: None, times, after_handling: true });
+    }
+
+    /// Shorthand for `times` 429s carrying `Retry-After: retry_after`
+    pub fn rate_limit(&self, method: Method, path: &str, retry_after: u64, times: usize) {
+        self.inject(Fault {
+            method: Some(method),
+            path: path.to_string(),
+            status: StatusCode::TOO_MANY_REQUESTS,
+            retry_after: Some(retry_after),
+            times,
+            after_handling: false,
+        });
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// How many requests matched `method path`, faults included
+    pub fn count(&self, method: &str, path: &str) -> usize {
+        let wanted = format!("{} {}", method, path);
+        self.requests().iter().filter(|r| **r == wanted).count()
+    }
+}
+
+fn new_playlist(id: &str, owner: &str, name: &str, tracks: Vec<FakeTrack>) -> FakePlaylist {
+    FakePlaylist {
+        id: id.to_string(),
+        name: name.to_string(),
+        owner: owner.to_string(),
+        description: String::new(),
+        public: false,
+        collaborative: false,
+        tracks,
+        image_url: None,
+        cover_uploads: Vec::new(),
+        snapshot: 1,
+    }
+}
+
+fn issue_tokens(state: &mut FakeState, user: &str) -> (String, String) {
+    let access = state.next_id("access-");
+    let refresh = state.next_id("refresh-");
+    state.access_tokens.insert(access.clone(), user.to_string());
+    state.refresh_tokens.insert(refresh.clone(), user.to_string());
+    (access, refresh)
+}
+
+async fn inject_faults(State(fake): State<FakeSpotify>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    let fault = {
+        let mut state = fake.state.lock().unwrap();
+        state.requests.push(format!("{} {}", request.method(), path));
+
+        let matching = state.faults.iter().position(|fault| fault.matches(request.method(), &path));
+        matching.map(|index| {
+            let fault = &mut state.faults[index];
+            fault.times -= 1;
+            let hit = fault.clone();
+            if fault.times == 0 {
+                state.faults.remove(index);
+            }
+            hit
+        })
+    };
+
+    match fault {
+        Some(fault) => {
+            if fault.after_handling {
+                next.run(request).await;
+            }
+            let body = Json(json!({ "error": { "status": fault.status.as_u16(), "message": "injected fault" } }));
+            match fault.retry_after {
+                Some(seconds) => (fault.status, [(header::RETRY_AFTER, seconds.to_string())], body).into_response(),
+                None => (fault.status, body).into_response(),
+            }
+        }
+        None => next.run(request).await,
+    }
+}
+
+fn api_error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": { "status": status.as_u16(), "message": message } }))).into_response()
+}
+
+/// The accounts service answers in OAuth's error shape rather than the Web API's
+fn oauth_error(status: StatusCode, error: &str, description: &str) -> Response {
+    (status, Json(json!({ "error": error, "error_description": description }))).into_response()
+}
+
+/// The user behind the bearer token, or the 401 Spotify would send
+fn authenticate(fake: &FakeSpotify, headers: &HeaderMap) -> Result<String, Response> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    fake.state
+        .lock()
+        .unwrap()
+        .access_tokens
+        .get(token)
+        .cloned()
+        .ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "The access token expired"))
+}
+
+fn paging(base: &str, items: Vec<Value>, total: usize, offset: usize, limit: usize) -> Value {
+    let link = |offset: usize| format!("{}?offset={}&limit={}", base, offset, limit);
+    json!({
+        "href": link(offset),
+        "items": items,
+        "limit": limit,
+        "offset": offset,
+        "total": total,
+        "next": (offset + limit < total).then(|| link(offset + limit)),
+        "previous": (offset > 0).then(|| link(offset.saturating_sub(limit))),
+    })
+}
+
+#[derive(Deserialize)]
+struct PageQuery {
+    limit: Option<usize>,
+    offset: Option<usize>,
+    additional_types: Option<String>,
+}
+
+impl PageQuery {
+    fn window(&self, max: usize) -> Result<(usize, usize), Response> {
+        let limit = self.limit.unwrap_or(20);
+        if limit == 0 || limit > max {
+            return Err(api_error(StatusCode::BAD_REQUEST, "Invalid limit"));
+        }
+        Ok((self.offset.unwrap_or(0), limit))
+    }
+}
+
+async fn authorize(State(fake): State<FakeSpotify>, Query(query): Query<HashMap<String, String>>) -> Response {
+    let (Some(redirect_uri), Some(state_param), Some(challenge)) =
+        (query.get("redirect_uri"), query.get("state"), query.get("code_challenge"))
+    else {
+        return api_error(StatusCode::BAD_REQUEST, "missing authorize parameters");
+    };
+
+    let mut state = fake.state.lock().unwrap();
+    let Some(user) = state.login_as.clone() else {
+        return Redirect::to(&format!("{}?error=access_denied&state={}", redirect_uri, state_param)).into_response();
+    };
+    let code = state.next_id("code-");
+    state.auth_codes.insert(code.clone(), (user, challenge.clone()));
+    Redirect::to(&format!("{}?code={}&state={}", redirect_uri, code, state_param)).into_response()
+}
+
+async fn token(State(fake): State<FakeSpotify>, Form(form): Form<HashMap<String, String>>) -> Response {
+    let mut state = fake.state.lock().unwrap();
+    let field = |key: &str| form.get(key).cloned().unwrap_or_default();
+
+    let (user, rotated_refresh) = match field("grant_type").as_str() {
+        "authorization_code" => {
+            let Some((user, challenge)) = state.auth_codes.remove(&field("code")) else {
+                return oauth_error(StatusCode::BAD_REQUEST, "invalid_grant", "Invalid authorization code");
+            };
+            if pkce::generate_code_challenge(&field("code_verifier")) != challenge {
+                return oauth_error(StatusCode::BAD_REQUEST, "invalid_grant", "code_verifier was incorrect");
+            }
+            (user, true)
+        }
+        "refresh_token" => match state.refresh_tokens.get(&field("refresh_token")) {
+            Some(user) => (user.clone(), false),
+            None => return oauth_error(StatusCode::BAD_REQUEST, "invalid_grant", "Invalid refresh token"),
+        },
+        _ => return oauth_error(StatusCode::BAD_REQUEST, "unsupported_grant_type", "grant_type must be authorization_code or refresh_token"),
+    };
+
+    let (access, refresh) = issue_tokens(&mut state, &user);
+    // like Spotify, a refresh grant usually doesn't hand out a new refresh token
+    Json(json!({
+        "access_token": access,
+        "token_type": "Bearer",
+        "scope": "playlist-read-private playlist-modify-private ugc-image-upload",
+        "expires_in": 3600,
+        "refresh_token": rotated_refresh.then_some(refresh),
+    }))
+    .into_response()
+}
+
+async fn me(State(fake): State<FakeSpotify>, headers: HeaderMap) -> Response {
+    let user = match authenticate(&fake, &headers) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let display_name = fake.state.lock().unwrap().users.get(&user).cloned().unwrap_or_default();
+    Json(json!({ "id": user, "display_name": display_name, "images": [] })).into_response()
+}
+
+async fn my_playlists(State(fake): State<FakeSpotify>, headers: HeaderMap, Query(page): Query<PageQuery>) -> Response {
+    let user = match authenticate(&fake, &headers) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let (offset, limit) = match page.window(MAX_PLAYLISTS_LIMIT) {
+        Ok(window) => window,
+        Err(response) => return response,
+    };
+
+    let state = fake.state.lock().unwrap();
+    let library = state.library.get(&user).cloned().unwrap_or_default();
+    let items = library
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .filter_map(|id| state.playlists.get(id))
+        .map(|playlist| playlist.to_json(&fake.base_url))
+        .collect();
+    Json(paging(&format!("{}/v1/me/playlists", fake.base_url), items, library.len(), offset, limit)).into_response()
+}
+
+async fn my_tracks(State(fake): State<FakeSpotify>, headers: HeaderMap, Query(page): Query<PageQuery>) -> Response {
+    let user = match authenticate(&fake, &headers) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let (offset, limit) = match page.window(MAX_SAVED_TRACKS_LIMIT) {
+        Ok(window) => window,
+        Err(response) => return response,
+    };
+
+    let state = fake.state.lock().unwrap();
+    let liked = state.liked.get(&user).cloned().unwrap_or_default();
+    let items = liked
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .map(|track| json!({ "added_at": "2024-01-01T00:00:00Z", "track": track.to_json() }))
+        .collect();
+    Json(paging(&format!("{}/v1/me/tracks", fake.base_url), items, liked.len(), offset, limit)).into_response()
+}
+
+async fn playlist(State(fake): State<FakeSpotify>, headers: HeaderMap, Path(id): Path<String>) -> Response {
+    if let Err(response) = authenticate(&fake, &headers) {
+        return response;
+    }
+    match fake.state.lock().unwrap().playlists.get(&id) {
+        Some(playlist) => Json(playlist.to_json(&fake.base_url)).into_response(),
+        None => api_error(StatusCode::NOT_FOUND, "Not found."),
+    }
+}
+
+#[derive(Deserialize)]
+struct ChangeDetails {
+    name: Option<String>,
+    public: Option<bool>,
+    description: Option<String>,
+}
+
+async fn change_details(
+    State(fake): State<FakeSpotify>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(body): Json<ChangeDetails>,
+) -> Response {
+    with_owned_playlist(&fake, &headers, &id, |state, id| {
+        let playlist = state.playlists.get_mut(&id).unwrap();
+        playlist.name = body.name.unwrap_or_else(|| playlist.name.clone());
+        playlist.public = body.public.unwrap_or(playlist.public);
+        playlist.description = body.description.unwrap_or_else(|| playlist.description.clone());
+        StatusCode::OK.into_response()
+    })
+}
+
+async fn playlist_tracks(
+    State(fake): State<FakeSpotify>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(page): Query<PageQuery>,
+) -> Response {
+    if let Err(response) = authenticate(&fake, &headers) {
+        return response;
+    }
+    let (offset, limit) = match page.window(MAX_TRACKS_LIMIT) {
+        Ok(window) => window,
+        Err(response) => return response,
+    };
+
+    let state = fake.state.lock().unwrap();
+    let Some(playlist) = state.playlists.get(&id) else {
+        return api_error(StatusCode::NOT_FOUND, "Not found.");
+    };
+    // `fields` filtering is not emulated, callers get the full objects
+    let episodes_wanted = page.additional_types.as_deref().is_some_and(|types| types.split(',').any(|t| t == "episode"));
+    let items = playlist
+        .tracks
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .map(|track| {
+            // like Spotify, episodes only come back to clients that say they can handle them
+            let item = if track.kind == "episode" && !episodes_wanted { Value::Null } else { track.to_json() };
+            json!({
+                "added_at": "2024-01-01T00:00:00Z",
+                "added_by": { "id": playlist.owner },
+                "is_local": track.is_local,
+                "track": item,
+            })
+        })
+        .collect();
+    let base = format!("{}/v1/playlists/{}/tracks", fake.base_url, id);
+    Json(paging(&base, items, playlist.tracks.len(), offset, limit)).into_response()
+}
+
+#[derive(Deserialize)]
+struct CreatePlaylist {
+    name: String,
+    public: Option<bool>,
+    description: Option<String>,
+}
+
+async fn create_playlist(
+    State(fake): State<FakeSpotify>,
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+    Json(body): Json<CreatePlaylist>,
+) -> Response {
+    let user = match authenticate(&fake, &headers) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    if user != user_id {
+        return api_error(StatusCode::FORBIDDEN, "You cannot create a playlist for another user");
+    }
+
+    let mut state = fake.state.lock().unwrap();
+    let id = state.next_id("pl");
+    let playlist = FakePlaylist {
+        description: body.description.unwrap_or_default(),
+        public: body.public.unwrap_or(true),
+        ..new_playlist(&id, &user, &body.name, Vec::new())
+    };
+    let created = playlist.to_json(&fake.base_url);
+    state.playlists.insert(id.clone(), playlist);
+    state.library.entry(user).or_default().insert(0, id);
+    (StatusCode::CREATED, Json(created)).into_response()
+}
+
+fn track_from_uri(state: &FakeState, uri: &str) -> FakeTrack {
+    // reuse what we know about the uri so metadata survives a copy
+    state
+        .playlists
+        .values()
+        .flat_map(|playlist| playlist.tracks.iter())
+        .find(|track| track.uri == uri)
+        .cloned()
+        .unwrap_or_else(|| {
+            let id = uri.rsplit(':').next().unwrap_or(uri);
+            FakeTrack { uri: uri.to_string(), ..FakeTrack::track(id, "unknown") }
+        })
+}
+
+/// Checks the bearer token belongs to the playlist's owner and hands the playlist to `f`
+fn with_owned_playlist(
+    fake: &FakeSpotify,
+    headers: &HeaderMap,
+    id: &str,
+    f: impl FnOnce(&mut FakeState, String) -> Response,
+) -> Response {
+    let user = match authenticate(fake, headers) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let mut state = fake.state.lock().unwrap();
+    match state.playlists.get(id) {
+        None => api_error(StatusCode::NOT_FOUND, "Not found."),
+        Some(playlist) if playlist.owner != user => api_error(StatusCode::FORBIDDEN, "You cannot modify this playlist"),
+        Some(_) => f(&mut state, id.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+struct AddTracks {
+    uris: Vec<String>,
+    position: Option<usize>,
+}
+
+async fn add_tracks(
+    State(fake): State<FakeSpotify>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(body): Json<AddTracks>,
+) -> Response {
+    if body.uris.len() > MAX_TRACKS_LIMIT {
+        return api_error(StatusCode::BAD_REQUEST, "You can add a maximum of 100 tracks per request.");
+    }
+    if body.uris.iter().any(|uri| uri.starts_with("spotify:local:")) {
+        return api_error(StatusCode::BAD_REQUEST, "Local files can't be added to playlists through the API");
+    }
+    with_owned_playlist(&fake, &headers, &id, |state, id| {
+        let tracks: Vec<FakeTrack> = body.uris.iter().map(|uri| track_from_uri(state, uri)).collect();
+        let playlist = state.playlists.get_mut(&id).unwrap();
+        let position = body.position.unwrap_or(playlist.tracks.len()).min(playlist.tracks.len());
+        playlist.tracks.splice(position..position, tracks);
+        playlist.snapshot += 1;
+        (StatusCode::CREATED, Json(json!({ "snapshot_id": playlist.snapshot_id() }))).into_response()
+    })
+}
+
+#[derive(Deserialize)]
+struct ReplaceOrReorder {
+    uris: Option<Vec<String>>,
+    range_start: Option<usize>,
+    insert_before: Option<usize>,
+    range_length: Option<usize>,
+    snapshot_id: Option<String>,
+}
+
+async fn replace_or_reorder_tracks(
+    State(fake): State<FakeSpotify>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(body): Json<ReplaceOrReorder>,
+) -> Response {
+    with_owned_playlist(&fake, &headers, &id, |state, id| {
+        if let Some(uris) = &body.uris {
+            if uris.len() > MAX_TRACKS_LIMIT {
+                return api_error(StatusCode::BAD_REQUEST, "You can set a maximum of 100 tracks per request.");
+            }
+            let tracks: Vec<FakeTrack> = uris.iter().map(|uri| track_from_uri(state, uri)).collect();
+            let playlist = state.playlists.get_mut(&id).unwrap();
+            playlist.tracks = tracks;
+            playlist.snapshot += 1;
+            return Json(json!({ "snapshot_id": playlist.snapshot_id() })).into_response();
+        }
+
+        let playlist = state.playlists.get_mut(&id).unwrap();
+        let (Some(start), Some(before)) = (body.range_start, body.insert_before) else {
+            return api_error(StatusCode::BAD_REQUEST, "Either uris or range_start and insert_before are required");
+        };
+        // Spotify takes any snapshot the playlist had and merges the move into what changed since;
+        // the fake applies it to the current items, which is the same as long as nothing did
+        if body.snapshot_id.as_ref().is_some_and(|snapshot| !playlist.had_snapshot(snapshot)) {
+            return api_error(StatusCode::BAD_REQUEST, "Invalid snapshot id");
+        }
+        let length = body.range_length.unwrap_or(1);
+        let len = playlist.tracks.len();
+        if start + length > len || before > len {
+            return api_error(StatusCode::BAD_REQUEST, "Index out of bounds");
+        }
+        let moved: Vec<FakeTrack> = playlist.tracks.drain(start..start + length).collect();
+        let insert_at = if before > start { before - length } else { before };
+        playlist.tracks.splice(insert_at..insert_at, moved);
+        playlist.snapshot += 1;
+        Json(json!({ "snapshot_id": playlist.snapshot_id() })).into_response()
+    })
+}
+
+async fn cover_images(State(fake): State<FakeSpotify>, headers: HeaderMap, Path(id): Path<String>) -> Response {
+    if let Err(response) = authenticate(&fake, &headers) {
+        return response;
+    }
+    match fake.state.lock().unwrap().playlists.get(&id) {
+        Some(playlist) => Json(playlist.to_json(&fake.base_url)["images"].clone()).into_response(),
+        None => api_error(StatusCode::NOT_FOUND, "Not found."),
+    }
+}
+
+async fn upload_cover(State(fake): State<FakeSpotify>, headers: HeaderMap, Path(id): Path<String>, body: Bytes) -> Response {
+    with_owned_playlist(&fake, &headers, &id, |state, id| {
+        let playlist = state.playlists.get_mut(&id).unwrap();
+        playlist.cover_uploads.push(String::from_utf8_lossy(&body).into_owned());
+        playlist.image_url = Some(format!("{}/images/{}.jpg", fake.base_url, id));
+        StatusCode::ACCEPTED.into_response()
+    })
+}
+
+/// Stands in for Spotify's image CDN, every cover is the same few bytes
+async fn image_file(Path(_name): Path<String>) -> Response {
+    ([(header::CONTENT_TYPE, "image/jpeg")], &b"\xff\xd8\xff\xe0fake-jpeg"[..]).into_response()
+}
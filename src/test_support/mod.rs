@@ -0,0 +1,87 @@
+//! Helpers for tests that drive server fns and the auth routes against [`FakeSpotify`].
+
+pub mod fake_spotify;
+
+use std::{collections::HashMap, future::Future, time::Duration};
+
+use axum::http::{header::COOKIE, Request};
+use dioxus::prelude::{DioxusServerContext, ProvideServerContext};
+
+use crate::api_models::{SpotifyTokenResponse, SpotifyTrackAlbumSimple, SpotifyTrackArtistsSimple, SpotifyTrackItem};
+use crate::auth::token::SessionTokens;
+use crate::config::{Config, FileConfig};
+use crate::server::AppState;
+use crate::storage::{self, Storage};
+
+pub use fake_spotify::{FakeSpotify, FakeTrack};
+
+/// Config pointing at the fake, with an in-memory database and (almost) no pauses between requests
+pub fn test_config(fake: &FakeSpotify, redirect_uri: &str) -> Config {
+    let env = HashMap::from([
+        ("SPOTIFY_CLIENT_ID", "test-client".to_string()),
+        ("SPOTIFY_CLIENT_SECRET", "test-secret".to_string()),
+        ("SPOTIFY_REDIRECT_URI", redirect_uri.to_string()),
+        ("SPOTIFY_API_BASE_URL", fake.api_base_url()),
+        ("SPOTIFY_ACCOUNTS_BASE_URL", fake.base_url.clone()),
+        ("DATABASE_PATH", storage::IN_MEMORY.to_string()),
+    ]);
+    let mut config = Config::from_sources(FileConfig::default(), |key| env.get(key).cloned()).expect("test config");
+    config.shuffle.chunk_delay_ms = 0;
+    config.spotify.retry.base_delay = Duration::from_millis(1);
+    config
+}
+
+pub fn test_app_state(config: Config) -> AppState {
+    let storage = Storage::open(&config.database_path, config.token_encryption_key).expect("in-memory storage");
+    AppState::new(config, storage)
+}
+
+/// Logs `user` in as if they had gone through the OAuth flow and returns the `Cookie` header value
+pub fn log_in(app_state: &AppState, fake: &FakeSpotify, user: &str) -> String {
+    let (access_token, refresh_token) = fake.issue_tokens(user);
+    let stored = app_state.storage.upsert_user(user, user).expect("store user");
+    let tokens = SessionTokens::from_response(
+        SpotifyTokenResponse {
+            access_token,
+            scope: "playlist-read-private playlist-modify-private".to_string(),
+            expires_in: 3600,
+            refresh_token: Some(refresh_token),
+        },
+        None,
+    );
+    let session_id = app_state.sessions.login(None, stored, tokens).expect("log in");
+    let set_cookie = app_state.sessions.cookie_for(&session_id, false);
+    set_cookie.split(';').next().unwrap().to_string()
+}
+
+/// Runs a server fn the way the fullstack handler would: with `app_state` provided and `cookie` on the request
+pub async fn call_server_fn<F: Future>(app_state: &AppState, cookie: &str, server_fn: F) -> F::Output {
+    let (parts, _) = Request::builder().header(COOKIE, cookie).body(()).unwrap().into_parts();
+    let context = DioxusServerContext::new(parts);
+    context.insert(app_state.clone());
+    ProvideServerContext::new(server_fn, context).await
+}
+
+/// A playlist item by `artist` on `album` with everything else left blank, for testing orderings
+pub fn track_item(id: &str, artist: &str, album: &str) -> SpotifyTrackItem {
+    SpotifyTrackItem {
+        id: Some(id.to_string()),
+        uri: format!("spotify:track:{}", id),
+        name: format!("Song {}", id),
+        item_type: Some("track".to_string()),
+        artists: vec![SpotifyTrackArtistsSimple { id: Some(artist.to_string()), name: artist.to_string() }],
+        album: Some(SpotifyTrackAlbumSimple {
+            id: Some(album.to_string()),
+            name: album.to_string(),
+            images: None,
+            release_date: None,
+            release_date_precision: None,
+        }),
+        duration_ms: 180_000,
+        explicit: false,
+        is_local: false,
+        popularity: None,
+        is_playable: None,
+        available_markets: None,
+    }
+}